@@ -1,7 +1,79 @@
-use std::collections::HashMap;
+#![allow(dead_code)]
+
+#[cfg(feature = "rational")]
+use num_bigint::BigInt;
+#[cfg(feature = "rational")]
+use num_rational::BigRational;
+#[cfg(feature = "rational")]
+use num_traits::{ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::time::{Duration, Instant};
 
 fn main() {
-    println!("Hello, Coreum!");
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    std::process::exit(run_cli(&args));
+}
+
+//Share of a denom's total supply a single tx may burn before it is flagged
+pub const LARGE_BURN_FRACTION: f64 = 0.01;
+
+//Ledger account credited with the burnt coins
+pub const BURN_ACCOUNT: &str = "__burn__";
+
+const USAGE: &str =
+    "usage: rust-task validate --input tx.json --balances balances.json --denoms denoms.json";
+
+//Runs a subcommand and returns the process exit code: 0 on success, 1 when the tx is rejected and
+//2 for bad arguments or unreadable files
+fn run_cli(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("validate") => match read_cli_inputs(&args[1..]) {
+            Ok((balances, definitions, multi_send_tx)) => {
+                match validate_transaction(balances, definitions, multi_send_tx) {
+                    Ok(()) => {
+                        println!("OK");
+                        0
+                    }
+                    Err(err) => {
+                        eprintln!("{:?}", err);
+                        1
+                    }
+                }
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                2
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
+
+//Reads the JSON files named by the --input, --balances and --denoms flags
+fn read_cli_inputs(
+    args: &[String],
+) -> Result<(Vec<Balance>, Vec<DenomDefinition>, MultiSend), String> {
+    let flag = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|idx| args.get(idx + 1))
+            .ok_or(format!("missing {}\n{}", name, USAGE))
+    };
+    fn read<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|err| format!("cannot read {}: {}", path, err))?;
+        serde_json::from_str(&json).map_err(|err| format!("cannot parse {}: {}", path, err))
+    }
+
+    let multi_send_tx = read(flag("--input")?)?;
+    let balances = read(flag("--balances")?)?;
+    let definitions = read(flag("--denoms")?)?;
+    Ok((balances, definitions, multi_send_tx))
 }
 
 // A user can submit a `MultiSend` transaction (similar to bank.MultiSend in cosmos sdk) to transfer multiple
@@ -10,77 +82,583 @@ fn main() {
 // denoms, in ethereum world they are called symbols.
 // The sum of input coins and output coins must match for every transaction.
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MultiSend {
     // inputs contain the list of accounts that want to send coins from, and how many coins from each account we want to send.
     inputs: Vec<Balance>,
     // outputs contains the list of accounts that we want to deposit coins into, and how many coins to deposit into
     // each account
     outputs: Vec<Balance>,
+    //Free-form note carried along like a cosmos tx memo. Never used in the computation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
 }
 
 impl MultiSend {
     //Validates the summation of i/o are identical.
-    pub fn validate_multi_send_tx(&self) -> Result<(), String> {
-        let mut multi_send_sum: (i128, i128) = (0, 0);
+    pub fn validate_multi_send_tx(&self) -> Result<(), TxError> {
+        //One-sided txs can't balance, report which side is missing rather than a sum mismatch
+        if !self.inputs.is_empty() && self.outputs.is_empty() {
+            return Err(TxError::NoOutputs);
+        }
+        if self.inputs.is_empty() && !self.outputs.is_empty() {
+            return Err(TxError::NoInputs);
+        }
+
         //Validate the summations of the i/o on the multi_send_tx prior to continuing
-        self.inputs.iter().for_each(|i| {
-            multi_send_sum.0 += i.coins.iter().fold(0, |_acc, coin| _acc + coin.amount);
-        });
-        self.outputs.iter().for_each(|o| {
-            multi_send_sum.1 += o.coins.iter().fold(0, |_acc, coin| _acc + coin.amount);
-        });
+        let multi_send_sum = (checked_sum(&self.inputs)?, checked_sum(&self.outputs)?);
 
         if multi_send_sum.0 != multi_send_sum.1 {
-            Err("Invalid Multi Send Tx".to_string())
+            Err(TxError::InvalidSum)
         } else {
             Ok(())
         }
     }
+
+    //Syntactic checks needing neither balances nor definitions: both sides non-empty, no negative
+    //amount, and inputs matching outputs per denom. Unknown denoms can't be detected here.
+    pub fn validate_structure(&self) -> Result<(), TxError> {
+        if self.inputs.is_empty() {
+            return Err(TxError::NoInputs);
+        }
+        if self.outputs.is_empty() {
+            return Err(TxError::NoOutputs);
+        }
+
+        //Denom -> inputs minus outputs
+        let mut net: BTreeMap<&str, i128> = BTreeMap::new();
+        let entries = self
+            .inputs
+            .iter()
+            .map(|input| (input, 1))
+            .chain(self.outputs.iter().map(|output| (output, -1)));
+        for (balance, sign) in entries {
+            for coin in balance.coins.iter() {
+                if coin.amount < 0 {
+                    return Err(TxError::NegativeAmount {
+                        address: balance.address.clone(),
+                        denom: coin.denom.clone(),
+                    });
+                }
+                let sum = net.entry(&coin.denom).or_insert(0);
+                *sum = sum
+                    .checked_add(sign * coin.amount)
+                    .ok_or(TxError::Overflow)?;
+            }
+        }
+        match net.values().all(|sum| *sum == 0) {
+            true => Ok(()),
+            false => Err(TxError::InvalidSum),
+        }
+    }
+
+    //Splits the tx into sub-transactions of at most `max_entries` inputs and `max_entries` outputs
+    //(at least 1), each balanced per denom. Every input amount is matched to output amounts of the
+    //same denom in listing order, and the resulting transfers are packed into chunks in that order.
+    //Fees are charged per chunk, so their rounding may differ from the whole tx. The tx must be
+    //balanced per denom (see `validate_structure`), amounts that can't be matched are left out.
+    pub fn chunk(&self, max_entries: usize) -> Vec<MultiSend> {
+        let max_entries = max_entries.max(1);
+
+        //(sender, recipient, denom, amount) transfers, per denom in order of first appearance
+        let mut transfers: Vec<(&str, &str, &str, i128)> = vec![];
+        let mut denoms: Vec<&str> = vec![];
+        for coin in self.inputs.iter().flat_map(|input| input.coins.iter()) {
+            if !denoms.contains(&coin.denom.as_str()) {
+                denoms.push(&coin.denom);
+            }
+        }
+        for denom in denoms {
+            let mut outputs = denom_pieces(&self.outputs, denom);
+            let mut next_output = 0;
+            for (sender, mut remaining) in denom_pieces(&self.inputs, denom) {
+                while remaining > 0 && next_output < outputs.len() {
+                    let (recipient, left) = &mut outputs[next_output];
+                    let amount = min(remaining, *left);
+                    transfers.push((sender, recipient, denom, amount));
+                    remaining -= amount;
+                    *left -= amount;
+                    if *left == 0 {
+                        next_output += 1;
+                    }
+                }
+            }
+        }
+
+        let empty = || MultiSend {
+            inputs: vec![],
+            outputs: vec![],
+            memo: self.memo.clone(),
+        };
+        let mut chunks = vec![];
+        let mut chunk = empty();
+        for (sender, recipient, denom, amount) in transfers {
+            let has = |balances: &[Balance], address: &str| {
+                balances.iter().any(|balance| balance.address == address)
+            };
+            let inputs = chunk.inputs.len() + !has(&chunk.inputs, sender) as usize;
+            let outputs = chunk.outputs.len() + !has(&chunk.outputs, recipient) as usize;
+            if inputs > max_entries || outputs > max_entries {
+                chunks.push(std::mem::replace(&mut chunk, empty()));
+            }
+            add_coin(&mut chunk.inputs, sender, denom, amount);
+            add_coin(&mut chunk.outputs, recipient, denom, amount);
+        }
+        if !chunk.inputs.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+
+    //Reorders the coins of every input/output by denom, so consecutive lookups hit the same map
+    //entries. Processing is order independent, so the balance changes are unaffected.
+    pub fn sorted_by_denom(mut self) -> MultiSend {
+        for balance in self.inputs.iter_mut().chain(self.outputs.iter_mut()) {
+            balance.coins.sort_by(|a, b| a.denom.cmp(&b.denom));
+        }
+        self
+    }
+
+    //Returns the canonical form of the tx: one entry per address sorted by address, one coin per denom
+    //sorted by denom, and no zero-amount coins. Semantically identical txs normalize to equal values.
+    pub fn normalize(&self) -> MultiSend {
+        MultiSend {
+            inputs: normalize_balances(&self.inputs),
+            outputs: normalize_balances(&self.outputs),
+            memo: self.memo.clone(),
+        }
+    }
+}
+
+//(address, amount) of every positive coin of the denom, in listing order
+fn denom_pieces<'a>(balances: &'a [Balance], denom: &str) -> Vec<(&'a str, i128)> {
+    balances
+        .iter()
+        .flat_map(|balance| {
+            balance
+                .coins
+                .iter()
+                .filter(|coin| coin.denom == denom && coin.amount > 0)
+                .map(|coin| (balance.address.as_str(), coin.amount))
+        })
+        .collect()
+}
+
+//Sets the senders' meta of a denom on the recipients' changes that are exactly their outputs
+fn carry_coin_meta(multi_send_tx: &MultiSend, balance_changes: &mut [Balance]) {
+    let mut denom_meta: HashMap<&str, Option<&serde_json::Value>> = HashMap::new();
+    for coin in multi_send_tx
+        .inputs
+        .iter()
+        .flat_map(|input| input.coins.iter())
+    {
+        let meta = denom_meta.entry(&coin.denom).or_insert(coin.meta.as_ref());
+        if *meta != coin.meta.as_ref() {
+            *meta = None;
+        }
+    }
+    let mut received: HashMap<(&str, &str), i128> = HashMap::new();
+    for output in multi_send_tx.outputs.iter() {
+        for coin in output.coins.iter() {
+            *received.entry((&output.address, &coin.denom)).or_insert(0) += coin.amount;
+        }
+    }
+
+    for balance in balance_changes.iter_mut() {
+        for coin in balance.coins.iter_mut() {
+            let key = (balance.address.as_str(), coin.denom.as_str());
+            if received.get(&key) == Some(&coin.amount) {
+                if let Some(Some(meta)) = denom_meta.get(coin.denom.as_str()) {
+                    coin.meta = Some((*meta).clone());
+                }
+            }
+        }
+    }
+}
+
+//Adds the amount to the address's coin of the denom, creating the entries as needed
+fn add_coin(balances: &mut Vec<Balance>, address: &str, denom: &str, amount: i128) {
+    let index = match balances
+        .iter()
+        .position(|balance| balance.address == address)
+    {
+        Some(index) => index,
+        None => {
+            balances.push(Balance {
+                address: address.to_string(),
+                coins: vec![],
+            });
+            balances.len() - 1
+        }
+    };
+    let coins = &mut balances[index].coins;
+    match coins.iter_mut().find(|coin| coin.denom == denom) {
+        Some(coin) => coin.amount += amount,
+        None => coins.push(Coin {
+            denom: denom.to_string(),
+            amount,
+            meta: None,
+        }),
+    }
+}
+
+//Sums every coin amount of the balances, failing instead of wrapping when the sum overflows
+fn checked_sum(balances: &[Balance]) -> Result<i128, TxError> {
+    balances
+        .iter()
+        .flat_map(|balance| balance.coins.iter())
+        .try_fold(0_i128, |acc, coin| acc.checked_add(coin.amount))
+        .ok_or(TxError::Overflow)
+}
+
+//A MultiSend whose inputs point at entries of a shared balance pool by index instead of naming the
+//address, for systems that track balances positionally
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexedMultiSend {
+    pub inputs: Vec<IndexedInput>,
+    pub outputs: Vec<Balance>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexedInput {
+    //Index of the sender's entry in the balance pool
+    pub balance_index: usize,
+    pub coins: Vec<Coin>,
+}
+
+impl IndexedMultiSend {
+    //Resolves every input's index against the pool into the address-based MultiSend
+    pub fn to_multi_send(&self, pool: &[Balance]) -> Result<MultiSend, TxError> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| match pool.get(input.balance_index) {
+                Some(balance) => Ok(Balance {
+                    address: balance.address.clone(),
+                    coins: input.coins.clone(),
+                }),
+                None => Err(TxError::InvalidBalanceIndex {
+                    index: input.balance_index,
+                }),
+            })
+            .collect::<Result<Vec<Balance>, TxError>>()?;
+        Ok(MultiSend {
+            inputs,
+            outputs: self.outputs.clone(),
+            memo: None,
+        })
+    }
+}
+
+//Original balances keyed by address then denom, so lookups don't scan the whole list
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Balances(BTreeMap<String, BTreeMap<String, i128>>);
+
+impl Balances {
+    //Amount held by the address for the denom, 0 if it holds none
+    pub fn get(&self, address: &str, denom: &str) -> i128 {
+        self.0
+            .get(address)
+            .and_then(|coins| coins.get(denom))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn credit(&mut self, address: &str, denom: &str, amount: i128) -> Result<(), TxError> {
+        accumulate(
+            self.0
+                .entry(address.to_string())
+                .or_default()
+                .entry(denom.to_string())
+                .or_insert(0),
+            amount,
+        )
+    }
+
+    pub fn debit(&mut self, address: &str, denom: &str, amount: i128) -> Result<(), TxError> {
+        self.credit(
+            address,
+            denom,
+            amount.checked_neg().ok_or(TxError::Overflow)?,
+        )
+    }
+
+    //Whether the address has an entry for the denom, even a zero one
+    pub fn contains(&self, address: &str, denom: &str) -> bool {
+        self.0
+            .get(address)
+            .is_some_and(|coins| coins.contains_key(denom))
+    }
+
+    //Back to the list form, ordered by address and denom
+    pub fn into_vec(self) -> Vec<Balance> {
+        self.0
+            .into_iter()
+            .map(|(address, coins)| Balance {
+                address,
+                coins: coins
+                    .into_iter()
+                    .map(|(denom, amount)| Coin {
+                        denom,
+                        amount,
+                        meta: None,
+                    })
+                    .collect::<Vec<Coin>>(),
+            })
+            .collect::<Vec<Balance>>()
+    }
+}
+
+//Repeated addresses or denoms are summed
+//Repeated entries for an address and denom are summed, failing if the sum overflows
+impl TryFrom<Vec<Balance>> for Balances {
+    type Error = TxError;
+
+    fn try_from(balances: Vec<Balance>) -> Result<Self, TxError> {
+        let mut map = Balances::default();
+        for balance in balances.iter() {
+            map.0.entry(balance.address.clone()).or_default();
+            for coin in balance.coins.iter() {
+                map.credit(&balance.address, &coin.denom, coin.amount)?;
+            }
+        }
+        Ok(map)
+    }
+}
+
+//Merges the balances per address and per denom, dropping zero amounts and empty entries
+fn normalize_balances(balances: &[Balance]) -> Vec<Balance> {
+    let mut merged: BTreeMap<String, BTreeMap<String, i128>> = BTreeMap::new();
+    for balance in balances.iter() {
+        let coins = merged.entry(balance.address.clone()).or_default();
+        for coin in balance.coins.iter() {
+            *coins.entry(coin.denom.clone()).or_insert(0) += coin.amount;
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(address, coins)| Balance {
+            address,
+            coins: coins
+                .into_iter()
+                .filter(|(_, amount)| *amount != 0)
+                .map(|(denom, amount)| Coin {
+                    denom,
+                    amount,
+                    meta: None,
+                })
+                .collect::<Vec<Coin>>(),
+        })
+        .filter(|balance| !balance.coins.is_empty())
+        .collect::<Vec<Balance>>()
+}
+
+#[cfg(test)]
+thread_local! {
+    //Number of TxData::prepared calls made by the current thread, see `TxData::prepared_calls`
+    static PREPARED_CALLS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
 }
 
 //Struct holding relevant data to efficiently validate/process the transaction
+#[derive(Clone)]
 pub struct TxData {
     multi_send_tx: MultiSend,
-    original_balances: Vec<Balance>,
     definitions: Vec<DenomDefinition>,
     non_issuer_input_sum_map: HashMap<String, i128>, //HashMap from denom -> non_issuer_input_sum
     non_issuer_output_sum_map: HashMap<String, i128>, //HashMap from denom -> non_issuer_input_sum
-    balances_map: HashMap<String, Balance>,          //Tracks the address balances
+    balances: Balances,                              //Tracks the address balances
     coin_balance_changes_map: HashMap<String, HashMap<String, i128>>, //Tracks the balance changes on an address to a specific coin
     denom_definitions_map: HashMap<String, DenomDefinition>, //Hashmap from denom -> definition
+    share_trace: Vec<ShareDetail>, //Raw and rounded burn shares, in input order
+    fee_shares: Vec<FeeShare>, //Burn & commission charged to each non-issuer send, in input order
+    tagged_changes: Vec<TaggedChange>, //Every balance change with its cause, in processing order
+    total_burn_map: HashMap<String, i128>, //HashMap from denom -> sum of the rounded burn shares
+    total_commission_map: HashMap<String, i128>, //HashMap from denom -> sum of the rounded commission shares
+    burnt_outputs_map: HashMap<String, i128>, //HashMap from denom -> amount sent to the burn addresses
+    fee_free: bool, //No send is charged a fee, so the plain accounting path is used
+    audit: Option<Vec<Mutation>>, //Mutations of coin_balance_changes_map, when auditing
+    bc_init_time: Duration, //Time spent populating the non-issuer sums
+    timings: Option<Timings>, //Phase timings, when collected
 }
 
 impl TxData {
     pub fn new(
         multi_send_tx: MultiSend,
-        original_balances: Vec<Balance>,
+        original_balances: Balances,
         definitions: Vec<DenomDefinition>,
     ) -> TxData {
+        //Pre-size the maps so large txs don't rehash while they are filled. The tx can't reference more
+        //denoms than are defined, nor touch more addresses than it has entries (plus the issuers).
+        let denom_count = definitions.len();
+        let entry_count =
+            multi_send_tx.inputs.len() + multi_send_tx.outputs.len() + definitions.len();
         Self {
             multi_send_tx,
-            original_balances,
+            balances: original_balances,
             definitions,
-            non_issuer_input_sum_map: HashMap::new(),
-            non_issuer_output_sum_map: HashMap::new(),
-            coin_balance_changes_map: HashMap::new(),
-            balances_map: HashMap::new(),
-            denom_definitions_map: HashMap::new(),
-        }
-    }
-    //Initializes a Hashmap from address to balance
-    pub fn initialize_balances_map(&mut self) {
-        let mut balances_map = HashMap::new();
-        self.original_balances.iter().for_each(|balance| {
-            balances_map.insert(balance.address.clone(), balance.clone());
-        });
+            non_issuer_input_sum_map: HashMap::with_capacity(denom_count),
+            non_issuer_output_sum_map: HashMap::with_capacity(denom_count),
+            coin_balance_changes_map: HashMap::with_capacity(entry_count),
+            denom_definitions_map: HashMap::with_capacity(denom_count),
+            share_trace: vec![],
+            fee_shares: vec![],
+            tagged_changes: vec![],
+            total_burn_map: HashMap::new(),
+            total_commission_map: HashMap::new(),
+            burnt_outputs_map: HashMap::new(),
+            fee_free: false,
+            audit: None,
+            bc_init_time: Duration::ZERO,
+            timings: None,
+        }
+    }
+
+    //Number of txs validated by `prepared` on the current thread, for tests to count validations
+    #[cfg(test)]
+    pub(crate) fn prepared_calls() -> usize {
+        PREPARED_CALLS.with(|count| count.get())
+    }
+
+    //Validates the tx and builds a fully initialized TxData, so the init functions can't be
+    //forgotten or called out of order
+    #[allow(deprecated)]
+    pub fn prepared(
+        multi_send_tx: MultiSend,
+        original_balances: Vec<Balance>,
+        definitions: Vec<DenomDefinition>,
+    ) -> Result<TxData, TxError> {
+        #[cfg(test)]
+        PREPARED_CALLS.with(|count| count.set(count.get() + 1));
+        multi_send_tx.validate_multi_send_tx()?;
+        //Without any definition every coin would be unknown, report the missing definitions instead
+        if definitions.is_empty() && !multi_send_tx.inputs.is_empty() {
+            return Err(TxError::NoDefinitions);
+        }
 
-        self.balances_map = balances_map;
+        let original_balances = Balances::try_from(original_balances)?;
+        let mut tx_data = TxData::new(multi_send_tx, original_balances, definitions);
+        tx_data.initialize_definitions_map();
+        tx_data.validate_known_denoms()?;
+        //The sums above are across denoms, so also check each denom balances and nothing is negative
+        //before the per-denom sums are taken
+        tx_data.multi_send_tx.validate_structure()?;
+        tx_data.initialize_fee_data()?;
+        tx_data.validate_commission_splits()?;
+        tx_data.validate_issuers()?;
+        Ok(tx_data)
+    }
+
+    //Rejects commission splits whose weights are negative or don't add up to 1.
+    ///NOTE: Must be called after `initialize_definitions_map`.
+    pub fn validate_commission_splits(&self) -> Result<(), TxError> {
+        for definition in self.denom_definitions_map.values() {
+            let split = &definition.commission_split;
+            let total_weight = split.iter().map(|(_, weight)| weight).sum::<f64>();
+            if !split.is_empty()
+                && ((total_weight - 1_f64).abs() > 1e-9
+                    || split.iter().any(|(_, weight)| *weight < 0_f64))
+            {
+                return Err(TxError::InvalidCommissionSplit {
+                    denom: definition.denom.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    //Rejects definitions with an empty issuer, which would exempt every input with an empty address
+    pub fn validate_issuers(&self) -> Result<(), TxError> {
+        for definition in self.definitions.iter() {
+            if definition.issuer.is_empty() {
+                return Err(TxError::EmptyField { field: "issuer" });
+            }
+            if definition.issuers.iter().any(|issuer| issuer.is_empty()) {
+                return Err(TxError::EmptyField { field: "issuers" });
+            }
+        }
+        Ok(())
+    }
+
+    //Rejects coins whose denom has no definition, since they would be credited without being debited.
+    ///NOTE: Must be called after `initialize_definitions_map`.
+    pub fn validate_known_denoms(&self) -> Result<(), TxError> {
+        for balance in self
+            .multi_send_tx
+            .inputs
+            .iter()
+            .chain(self.multi_send_tx.outputs.iter())
+        {
+            for coin in balance.coins.iter() {
+                if !self.denom_definitions_map.contains_key(&coin.denom) {
+                    return Err(TxError::UnknownDenom {
+                        denom: coin.denom.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Initialized TxData for analysing a tx without balances, skipping the tx validation.
+    //The non-issuer sums are always populated since the callers read them directly.
+    #[allow(deprecated)]
+    fn unvalidated(
+        multi_send_tx: &MultiSend,
+        definitions: &[DenomDefinition],
+    ) -> Result<TxData, TxError> {
+        let mut tx_data = TxData::new(
+            multi_send_tx.clone(),
+            Balances::default(),
+            definitions.to_vec(),
+        );
+        tx_data.initialize_definitions_map();
+        tx_data.initialize_bc_data()?;
+        Ok(tx_data)
+    }
+
+    //Returns (total_bc, non_issuer_input_sum) for a denom, i.e. the base the fees are charged on and
+    //the sum it is distributed over
+    fn bc_base(&self, denom: &str) -> (i128, i128) {
+        let non_issuer_input_sum = self
+            .non_issuer_input_sum_map
+            .get(denom)
+            .copied()
+            .unwrap_or(0);
+        let non_issuer_output_sum = self
+            .non_issuer_output_sum_map
+            .get(denom)
+            .copied()
+            .unwrap_or(0);
+        (
+            min(non_issuer_input_sum, non_issuer_output_sum),
+            non_issuer_input_sum,
+        )
+    }
+
+    //Populates the commission & burn rate data, unneeded when no send is charged a fee.
+    ///NOTE: Must be called after `initialize_definitions_map`.
+    #[allow(deprecated)]
+    fn initialize_fee_data(&mut self) -> Result<(), TxError> {
+        self.fee_free = self.multi_send_tx.inputs.iter().all(|input| {
+            input.coins.iter().all(|coin| {
+                self.denom_definitions_map
+                    .get(&coin.denom)
+                    .is_none_or(|definition| send_is_fee_free(definition, &input.address))
+            })
+        });
+        if !self.fee_free {
+            let start = Instant::now();
+            self.initialize_bc_data()?;
+            self.bc_init_time = start.elapsed();
+        }
+        Ok(())
     }
 
     //Initializes HashMap from denom -> definition
+    #[deprecated(note = "use `TxData::prepared`, which runs the whole init sequence")]
     pub fn initialize_definitions_map(&mut self) {
-        let mut denominations_map = HashMap::new();
+        let mut denominations_map = HashMap::with_capacity(self.definitions.len());
         self.definitions.iter().for_each(|definition| {
             denominations_map.insert(definition.denom.clone(), definition.clone());
         });
@@ -89,20 +667,19 @@ impl TxData {
 
     //Initializes the burn & commission data necessary for burn/commision calculations.
     ///NOTE: Must be called after the prior 2 initialization functions to initialize the HashMaps.
-    pub fn initialize_bc_data(&mut self) {
+    #[deprecated(note = "use `TxData::prepared`, which runs the whole init sequence")]
+    pub fn initialize_bc_data(&mut self) -> Result<(), TxError> {
         //Populate non_issuer_input_sum_map
         for input in self.multi_send_tx.inputs.iter() {
             for coin in input.coins.iter() {
                 if let Some(definition) = self.denom_definitions_map.get(&coin.denom) {
-                    if input.address != definition.issuer {
-                        if let Some(amount) =
-                            self.non_issuer_input_sum_map.get_mut(&definition.denom)
-                        {
-                            *amount += coin.amount;
-                        } else {
+                    if !definition.is_issuer(&input.address) {
+                        accumulate(
                             self.non_issuer_input_sum_map
-                                .insert(definition.denom.clone(), coin.amount);
-                        }
+                                .entry(definition.denom.clone())
+                                .or_insert(0),
+                            coin.amount,
+                        )?;
                     }
                 }
             }
@@ -112,55 +689,1116 @@ impl TxData {
         for output in self.multi_send_tx.outputs.iter() {
             for coin in output.coins.iter() {
                 if let Some(definition) = self.denom_definitions_map.get(&coin.denom) {
-                    if output.address != definition.issuer {
-                        if let Some(amount) =
-                            self.non_issuer_output_sum_map.get_mut(&definition.denom)
-                        {
-                            *amount += coin.amount;
-                        } else {
+                    if !definition.is_issuer(&output.address) {
+                        accumulate(
                             self.non_issuer_output_sum_map
-                                .insert(definition.denom.clone(), coin.amount);
-                        }
+                                .entry(definition.denom.clone())
+                                .or_insert(0),
+                            coin.amount,
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Rejects the tx if any denom it sends charges more than 100% in combined fees.
+    ///NOTE: Must be called after `initialize_definitions_map`.
+    pub fn validate_fee_rates(&self) -> Result<(), TxError> {
+        for input in self.multi_send_tx.inputs.iter() {
+            for coin in input.coins.iter() {
+                if let Some(definition) = self.denom_definitions_map.get(&coin.denom) {
+                    if definition.burn_rate + definition.commission_rate > 1_f64 {
+                        return Err(TxError::ExcessiveFees {
+                            denom: coin.denom.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Rejects the tx if it burns more of a denom than its budget, checking the denoms in order.
+    ///NOTE: Must be called after the output loop.
+    pub fn validate_burn_budgets(&self, budgets: &HashMap<String, i128>) -> Result<(), TxError> {
+        let budgets = budgets.iter().collect::<BTreeMap<&String, &i128>>();
+        for (denom, budget) in budgets {
+            let burn = self.total_burn_map.get(denom).unwrap_or(&0)
+                + self.burnt_outputs_map.get(denom).unwrap_or(&0);
+            if burn > *budget {
+                return Err(TxError::BurnBudgetExceeded {
+                    denom: denom.clone(),
+                    burn,
+                    budget: *budget,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    //Rejects the tx if a sender has no original balance entry for a denom it sends
+    pub fn validate_balance_entries(&self) -> Result<(), TxError> {
+        for input in self.multi_send_tx.inputs.iter() {
+            for coin in input.coins.iter() {
+                if !self.balances.contains(&input.address, &coin.denom) {
+                    return Err(TxError::MissingBalanceEntry {
+                        address: input.address.clone(),
+                        denom: coin.denom.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Share of each non-issuer input coin whose denom has an exact rate, keyed by (input index, coin
+    //index): roundup(total_bc * rate * amount / non_issuer_input_sum) computed without any float.
+    #[cfg(feature = "rational")]
+    pub fn rational_shares(
+        &self,
+        rates: &HashMap<String, RationalRate>,
+        rate: impl Fn(&RationalRate) -> &BigRational,
+    ) -> Result<HashMap<(usize, usize), i128>, TxError> {
+        let mut shares = HashMap::new();
+        for (input_idx, input) in self.multi_send_tx.inputs.iter().enumerate() {
+            for (coin_idx, coin) in input.coins.iter().enumerate() {
+                let (Some(definition), Some(rates)) = (
+                    self.denom_definitions_map.get(&coin.denom),
+                    rates.get(&coin.denom),
+                ) else {
+                    continue;
+                };
+                if definition.is_issuer(&input.address) {
+                    continue;
+                }
+                let (total_bc, non_issuer_input_sum) = self.bc_base(&coin.denom);
+                let share = match non_issuer_input_sum {
+                    0 => 0,
+                    _ => rational_ceil(
+                        BigRational::from_integer(BigInt::from(total_bc))
+                            * rate(rates)
+                            * BigInt::from(coin.amount)
+                            / BigInt::from(non_issuer_input_sum),
+                    )?,
+                };
+                shares.insert((input_idx, coin_idx), share);
+            }
+        }
+        Ok(shares)
+    }
+
+    //Redecides whether the tx is fee-free, with the exact rates in place of the float ones of their
+    //denoms, and populates the non-issuer sums if it no longer is.
+    ///NOTE: Must be called after `initialize_fee_data`.
+    #[cfg(feature = "rational")]
+    #[allow(deprecated)]
+    fn recheck_fee_free(&mut self, rates: &HashMap<String, RationalRate>) -> Result<(), TxError> {
+        let fee_free = self.multi_send_tx.inputs.iter().all(|input| {
+            input.coins.iter().all(|coin| {
+                match (
+                    self.denom_definitions_map.get(&coin.denom),
+                    rates.get(&coin.denom),
+                ) {
+                    (Some(definition), Some(rate)) => send_is_fee_free_with(
+                        definition,
+                        &input.address,
+                        rate.burn.is_zero(),
+                        rate.commission.is_zero(),
+                    ),
+                    (Some(definition), None) => send_is_fee_free(definition, &input.address),
+                    (None, _) => true,
+                }
+            })
+        });
+        if self.fee_free && !fee_free {
+            self.initialize_bc_data()?;
+        }
+        self.fee_free = fee_free;
+        Ok(())
+    }
+
+    //Rounded share of each non-issuer input coin, keyed by (input index, coin index), such that each
+    //denom's shares add up to exactly roundup(total_bc * rate) rather than a sum of per-share ceilings.
+    //Every share gets its floor, and the leftover units go one each to the largest fractional remainders.
+    //Equal remainders are broken by address in lexicographic order, then by input order, so the result
+    //doesn't depend on how the inputs are listed.
+    pub fn exact_total_shares(
+        &self,
+        rate: impl Fn(&DenomDefinition) -> f64,
+    ) -> HashMap<(usize, usize), i128> {
+        let mut shares = HashMap::new();
+        let mut remainders: HashMap<&str, Vec<(f64, &str, usize, usize)>> = HashMap::new();
+
+        for (input_idx, input) in self.multi_send_tx.inputs.iter().enumerate() {
+            for (coin_idx, coin) in input.coins.iter().enumerate() {
+                if let Some(definition) = self.denom_definitions_map.get(&coin.denom) {
+                    if !definition.is_issuer(&input.address) {
+                        let (total_bc, non_issuer_input_sum) = self.bc_base(&coin.denom);
+                        let raw = raw_share(
+                            coin.amount,
+                            rate(definition),
+                            total_bc,
+                            non_issuer_input_sum,
+                        );
+                        shares.insert((input_idx, coin_idx), raw.floor() as i128);
+                        remainders.entry(&coin.denom).or_default().push((
+                            raw - raw.floor(),
+                            &input.address,
+                            input_idx,
+                            coin_idx,
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (denom, mut entries) in remainders {
+            let (total_bc, _) = self.bc_base(denom);
+            let total = roundup(total_bc as f64 * rate(&self.denom_definitions_map[denom]));
+            let floored: i128 = entries
+                .iter()
+                .map(|(_, _, input_idx, coin_idx)| shares[&(*input_idx, *coin_idx)])
+                .sum();
+            entries.sort_by(|a, b| {
+                b.0.total_cmp(&a.0)
+                    .then_with(|| a.1.cmp(b.1))
+                    .then_with(|| (a.2, a.3).cmp(&(b.2, b.3)))
+            });
+            for (_, _, input_idx, coin_idx) in
+                entries.iter().take((total - floored).max(0) as usize)
+            {
+                *shares.entry((*input_idx, *coin_idx)).or_insert(0) += 1;
+            }
+        }
+
+        shares
+    }
+
+    //Splits each denom's total fees across its non-issuer outputs proportionally to their amounts.
+    //Flooring leaves a few units over, which are taken one by one from the outputs in order.
+    ///NOTE: Must be called after the input loop has filled the burn & commission totals.
+    pub fn gross_output_deductions(&self) -> Result<HashMap<(usize, usize), i128>, TxError> {
+        let mut deductions = HashMap::new();
+        let mut withheld: HashMap<String, i128> = HashMap::new();
+        let mut entries: Vec<(usize, usize, &Coin)> = vec![];
+
+        for (output_idx, output) in self.multi_send_tx.outputs.iter().enumerate() {
+            for (coin_idx, coin) in output.coins.iter().enumerate() {
+                if let Some(definition) = self.denom_definitions_map.get(&coin.denom) {
+                    if !definition.is_issuer(&output.address) {
+                        entries.push((output_idx, coin_idx, coin));
                     }
                 }
             }
         }
+
+        for (output_idx, coin_idx, coin) in entries.iter() {
+            let fees = self.total_fees(&coin.denom);
+            let non_issuer_output_sum = self.non_issuer_output_sum_map[&coin.denom];
+            if fees > 0 && non_issuer_output_sum > 0 {
+                let deduction =
+                    fees.checked_mul(coin.amount).ok_or(TxError::Overflow)? / non_issuer_output_sum;
+                accumulate(withheld.entry(coin.denom.clone()).or_insert(0), deduction)?;
+                deductions.insert((*output_idx, *coin_idx), deduction);
+            }
+        }
+
+        for (output_idx, coin_idx, coin) in entries.iter() {
+            let withheld = withheld.entry(coin.denom.clone()).or_insert(0);
+            let deduction = deductions.entry((*output_idx, *coin_idx)).or_insert(0);
+            if *withheld < self.total_fees(&coin.denom) && *deduction < coin.amount {
+                *withheld += 1;
+                *deduction += 1;
+            }
+        }
+
+        Ok(deductions)
+    }
+
+    //Sum of the burn and commission charged on a denom so far
+    fn total_fees(&self, denom: &str) -> i128 {
+        self.total_burn_map.get(denom).unwrap_or(&0)
+            + self.total_commission_map.get(denom).unwrap_or(&0)
     }
 
     //Collect the nested hashmap into a Vec<Balance>
     pub fn collect_balance_changes(self) -> Vec<Balance> {
-        self.coin_balance_changes_map
+        let mut balance_changes = self
+            .coin_balance_changes_map
             .into_iter()
             .map(|(address, v)| Balance {
                 address,
                 coins: v
                     .into_iter()
-                    .map(|(denom, amount)| Coin { denom, amount })
+                    .map(|(denom, amount)| Coin {
+                        denom,
+                        amount,
+                        meta: None,
+                    })
                     .collect::<Vec<Coin>>(),
             })
-            .collect::<Vec<Balance>>()
+            .collect::<Vec<Balance>>();
+        //The map iteration order is random, so sort it away to keep the output reproducible
+        balance_changes.sort_by(|a, b| a.address.cmp(&b.address));
+        for balance in balance_changes.iter_mut() {
+            balance.coins.sort_by(|a, b| a.denom.cmp(&b.denom));
+        }
+        balance_changes
+    }
+
+    //Consume the tx data into the detailed result
+    pub fn into_result(mut self) -> TxResult {
+        let share_trace = std::mem::take(&mut self.share_trace);
+        let fee_shares = std::mem::take(&mut self.fee_shares);
+        let tagged_changes = std::mem::take(&mut self.tagged_changes);
+        let multi_send_tx = self.multi_send_tx.clone();
+        let mut warnings = self.unused_definition_warnings();
+        warnings.extend(self.large_burn_warnings());
+        let stats = self.processing_stats();
+        let rounding_gain = self.rounding_gain();
+        let definitions = std::mem::take(&mut self.definitions);
+        let audit = self.audit.take().unwrap_or_default();
+        let timings = self.timings.take();
+        let burnt_outputs = std::mem::take(&mut self.burnt_outputs_map);
+        let mut balance_changes = self.collect_balance_changes();
+        carry_coin_meta(&multi_send_tx, &mut balance_changes);
+        let result = TxResult {
+            balance_changes,
+            share_trace,
+            warnings,
+            stats,
+            rounding_gain,
+            fee_shares,
+            tagged_changes,
+            multi_send_tx,
+            definitions,
+            audit,
+            timings,
+            burnt_outputs,
+        };
+        //Each share is credited as rounded (Overshoot), so no reconciliation may leave a collector short
+        for denom in result.rounding_gain.keys() {
+            debug_assert_eq!(verify_commission_credit(&result, denom), Ok(()));
+        }
+        result
+    }
+
+    //Per denom, how much more the rounded-up shares (burn + commission) collected than the floored
+    //theoretical fee min(non_issuer_input_sum, non_issuer_output_sum) * (burn_rate + commission_rate).
+    ///NOTE: Must be called after the input loop.
+    pub fn rounding_gain(&self) -> HashMap<String, i128> {
+        self.total_burn_map
+            .keys()
+            .filter_map(|denom| {
+                let definition = self.denom_definitions_map.get(denom)?;
+                let total_bc = min(
+                    *self.non_issuer_input_sum_map.get(denom).unwrap_or(&0),
+                    *self.non_issuer_output_sum_map.get(denom).unwrap_or(&0),
+                );
+                let theoretical =
+                    (total_bc as f64 * (definition.burn_rate + definition.commission_rate)).floor();
+                Some((denom.clone(), self.total_fees(denom) - theoretical as i128))
+            })
+            .collect::<HashMap<String, i128>>()
+    }
+
+    //Counters describing the work done on the tx.
+    ///NOTE: Must be called after the input & output loops.
+    pub fn processing_stats(&self) -> ProcessingStats {
+        let mut denoms = HashSet::new();
+        for balance in self
+            .multi_send_tx
+            .inputs
+            .iter()
+            .chain(self.multi_send_tx.outputs.iter())
+        {
+            for coin in balance.coins.iter() {
+                denoms.insert(coin.denom.as_str());
+            }
+        }
+
+        ProcessingStats {
+            denoms_processed: denoms.len(),
+            accounts_touched: self.coin_balance_changes_map.len(),
+            total_burnt: self.total_burn_map.values().sum::<i128>()
+                + self.burnt_outputs_map.values().sum::<i128>(),
+            total_commission: self.total_commission_map.values().sum(),
+            rejected: 0,
+        }
+    }
+
+    //Flags definitions for denoms that no input or output references, which may hint at a config mismatch
+    pub fn unused_definition_warnings(&self) -> Vec<TxWarning> {
+        self.definitions
+            .iter()
+            .filter(|definition| {
+                !self
+                    .multi_send_tx
+                    .inputs
+                    .iter()
+                    .chain(self.multi_send_tx.outputs.iter())
+                    .any(|balance| {
+                        balance
+                            .coins
+                            .iter()
+                            .any(|coin| coin.denom == definition.denom)
+                    })
+            })
+            .map(|definition| TxWarning::UnusedDefinition {
+                denom: definition.denom.clone(),
+            })
+            .collect::<Vec<TxWarning>>()
+    }
+
+    //Denoms whose burn in the tx exceeds LARGE_BURN_FRACTION of their known total supply.
+    ///NOTE: Must be called after the input loop.
+    pub fn large_burn_warnings(&self) -> Vec<TxWarning> {
+        self.definitions
+            .iter()
+            .filter_map(|definition| {
+                let supply = definition.total_supply.filter(|supply| *supply > 0)?;
+                let burnt = *self.total_burn_map.get(&definition.denom)?
+                    + self.burnt_outputs_map.get(&definition.denom).unwrap_or(&0);
+                let fraction = burnt as f64 / supply as f64;
+                (fraction > LARGE_BURN_FRACTION).then(|| TxWarning::LargeBurnFraction {
+                    denom: definition.denom.clone(),
+                    fraction,
+                })
+            })
+            .collect::<Vec<TxWarning>>()
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Coin {
-    pub denom: String,
-    pub amount: i128,
+//Reasons a transaction is rejected
+#[derive(Clone, Debug, PartialEq)]
+pub enum TxError {
+    InvalidSum,
+    InsufficientBalance {
+        address: String,
+        denom: String,
+        amount: i128,
+        fees: i128,
+        available: i128,
+    },
+    ExcessiveFees {
+        denom: String,
+    },
+    NegativeAmount {
+        address: String,
+        denom: String,
+    },
+    TooManyEntries {
+        kind: &'static str,
+        count: usize,
+        limit: usize,
+    },
+    NoInputs,
+    NoOutputs,
+    NoSolution {
+        denom: String,
+    },
+    UnknownDenom {
+        denom: String,
+    },
+    DustOutput {
+        denom: String,
+        amount: i128,
+    },
+    InvalidSnapshot {
+        reason: String,
+    },
+    InvalidNdjson {
+        line: usize,
+        reason: String,
+    },
+    Overflow,
+    BurnMismatch {
+        denom: String,
+        expected: i128,
+        computed: i128,
+    },
+    InvalidBalanceIndex {
+        index: usize,
+    },
+    InternalInvariant {
+        reason: String,
+    },
+    InvalidCommissionSplit {
+        denom: String,
+    },
+    NoDefinitions,
+    OutputSumMismatch {
+        denom: String,
+        expected: i128,
+        credited: i128,
+    },
+    CommissionMismatch {
+        denom: String,
+        owed: i128,
+        credited: i128,
+    },
+    MissingBalanceEntry {
+        address: String,
+        denom: String,
+    },
+    BurnBudgetExceeded {
+        denom: String,
+        burn: i128,
+        budget: i128,
+    },
+    EmptyField {
+        field: &'static str,
+    },
+    InvalidAmount {
+        denom: String,
+        amount: String,
+    },
+    TransferCapExceeded {
+        denom: String,
+        amount: i128,
+        cap: i128,
+    },
+    ZeroRateDenominator,
 }
 
-#[derive(Clone, Debug)]
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::InvalidSum => write!(f, "Invalid Multi Send Tx"),
+            TxError::InsufficientBalance { address, denom, .. } => write!(
+                f,
+                "Inssuficient wallet balance on {} for coin {}",
+                address, denom
+            ),
+            TxError::ExcessiveFees { denom } => write!(
+                f,
+                "Combined burn and commission rate exceeds 100% for coin {}",
+                denom
+            ),
+            TxError::NegativeAmount { address, denom } => {
+                write!(f, "Negative amount on {} for coin {}", address, denom)
+            }
+            TxError::TooManyEntries { kind, count, limit } => {
+                write!(
+                    f,
+                    "Too many {}: {} exceeds the limit of {}",
+                    kind, count, limit
+                )
+            }
+            TxError::NoInputs => write!(f, "Multi Send Tx has no inputs"),
+            TxError::NoSolution { denom } => write!(
+                f,
+                "No input amount delivers the requested outputs for coin {}",
+                denom
+            ),
+            TxError::NoOutputs => write!(f, "Multi Send Tx has no outputs"),
+            TxError::UnknownDenom { denom } => write!(f, "No definition for coin {}", denom),
+            TxError::DustOutput { denom, amount } => write!(
+                f,
+                "Output of {} is below the minimum transfer for coin {}",
+                amount, denom
+            ),
+            TxError::InvalidSnapshot { reason } => write!(f, "Invalid node snapshot: {}", reason),
+            TxError::InvalidNdjson { line, reason } => {
+                write!(f, "Invalid transaction on line {}: {}", line, reason)
+            }
+            TxError::Overflow => write!(f, "Multi Send Tx amounts overflow"),
+            TxError::BurnMismatch {
+                denom,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "Burn of {} computed for coin {} differs from the expected {}",
+                computed, denom, expected
+            ),
+            TxError::InvalidBalanceIndex { index } => {
+                write!(f, "No balance entry at index {}", index)
+            }
+            TxError::InternalInvariant { reason } => {
+                write!(f, "Internal invariant violated: {}", reason)
+            }
+            TxError::NoDefinitions => write!(f, "No denom definitions were given"),
+            TxError::OutputSumMismatch {
+                denom,
+                expected,
+                credited,
+            } => write!(
+                f,
+                "Non-issuer recipients were credited {} instead of {} for coin {}",
+                credited, expected, denom
+            ),
+            TxError::CommissionMismatch {
+                denom,
+                owed,
+                credited,
+            } => write!(
+                f,
+                "Commission collectors were credited {} instead of the {} owed for coin {}",
+                credited, owed, denom
+            ),
+            TxError::BurnBudgetExceeded {
+                denom,
+                burn,
+                budget,
+            } => write!(
+                f,
+                "Burn of {} exceeds the budget of {} for coin {}",
+                burn, budget, denom
+            ),
+            TxError::MissingBalanceEntry { address, denom } => write!(
+                f,
+                "Sender {} has no balance entry for coin {}",
+                address, denom
+            ),
+            TxError::TransferCapExceeded { denom, amount, cap } => write!(
+                f,
+                "Output of {} exceeds the transfer cap of {} for coin {}",
+                amount, cap, denom
+            ),
+            TxError::InvalidAmount { denom, amount } => {
+                write!(f, "Invalid amount {} for coin {}", amount, denom)
+            }
+            TxError::EmptyField { field } => write!(f, "Denom definition has an empty {}", field),
+            TxError::ZeroRateDenominator => write!(f, "Rate has a zero denominator"),
+            TxError::InvalidCommissionSplit { denom } => write!(
+                f,
+                "Commission split weights must be non-negative and sum to 1 for coin {}",
+                denom
+            ),
+        }
+    }
+}
+
+impl TxError {
+    //Longer, user-facing description of the rejection than the Display form
+    pub fn explain(&self) -> String {
+        match self {
+            TxError::InvalidSum => "The inputs and outputs of the transaction don't add up to the same amount for every coin. Each coin sent must be received in full.".to_string(),
+            TxError::InsufficientBalance {
+                address,
+                denom,
+                amount,
+                fees,
+                available,
+            } => format!(
+                "Account '{}' tried to send {} {} plus {} in fees, but only holds {}. The transaction needs {} {} from this account.",
+                address,
+                amount,
+                denom,
+                fees,
+                available,
+                amount + fees,
+                denom
+            ),
+            TxError::ExcessiveFees { denom } => format!(
+                "The burn and commission rates of {} add up to more than 100%. This is most likely a misconfigured definition.",
+                denom
+            ),
+            TxError::NegativeAmount { address, denom } => format!(
+                "The entry of '{}' for {} has a negative amount. Amounts must be zero or more.",
+                address, denom
+            ),
+            TxError::UnknownDenom { denom } => format!(
+                "The transaction moves {}, but no definition was given for it. Every coin needs an issuer and rates.",
+                denom
+            ),
+            TxError::DustOutput { denom, amount } => format!(
+                "An output of {} {} is below the minimum transfer for that coin. Send a larger amount or drop the output.",
+                amount, denom
+            ),
+            TxError::MissingBalanceEntry { address, denom } => format!(
+                "Account '{}' sends {}, but the original balances have no entry for it. Strict mode requires one, even for a zero balance, so a balance that failed to load isn't taken as 0.",
+                address, denom
+            ),
+            TxError::NoDefinitions => "No coin definitions were given, so none of the coins sent can be priced. Pass the definitions of the coins in the transaction.".to_string(),
+            _ => format!("{}.", self),
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+//Optional behaviour when processing a transaction. The default matches `calculate_balance_changes`.
+#[derive(Clone, Debug, Default)]
+pub struct TxOptions {
+    //Reject the tx if a denom's burn_rate + commission_rate is above 1.0 (likely a config error). Always
+    //checked under `AmountSemantics::Gross`, where such fees would leave a recipient with a debit.
+    pub reject_fee_over_100_percent: bool,
+    //Whether fees are charged on top of the input amounts or deducted from them
+    pub amount_semantics: AmountSemantics,
+    //Upper bounds on the number of input/output entries, to bound the work done per tx
+    pub max_inputs: Option<usize>,
+    pub max_outputs: Option<usize>,
+    //Raw burn shares below this value are waived (charged 0) instead of being rounded up to a whole unit
+    pub min_burn_floor: Option<f64>,
+    //Match denoms ignoring case and surrounding whitespace. Every denom in the definitions, tx and
+    //balances is trimmed and lowercased before processing, so results use the normalized denoms
+    //(and a BalanceStore is queried with them).
+    pub case_insensitive_denoms: bool,
+    //Smallest amount an output may be credited per denom; smaller credits are rejected as dust
+    pub min_transfer: HashMap<String, i128>,
+    //Denom -> largest amount a single output coin may carry, e.g. a regulatory cap
+    pub max_transfer: HashMap<String, i128>,
+    //Leave the issuers' changes in the denoms they issue out of the returned balance changes (they are
+    //still used for the math). What an issuer receives of other denoms is kept.
+    pub exclude_issuers_from_output: bool,
+    //How the rounded burn/commission shares are derived from the raw ones
+    pub share_rounding: ShareRounding,
+    //Record every mutation of the balance changes map in `TxResult::audit`
+    pub audit: bool,
+    //Slack subtracted from the per-share and issuer fees before rounding them up, so a share just
+    //above an integer through float error (e.g. 6.000000000000001) isn't charged an extra unit.
+    //0 keeps the exact ceiling.
+    pub ceil_epsilon: f64,
+    //Time the processing phases into `TxResult::timings`
+    pub collect_timings: bool,
+    //Reject the tx with `TxError::MissingBalanceEntry` when a sender has no entry for a denom it sends
+    //in the original balances, instead of taking its balance as 0. The entries are looked up in the
+    //original balances, so leave it off when running against a `BalanceStore`.
+    pub strict_balance_entries: bool,
+    //Settlement netting: leave out the balance changes with |net change| <= net_threshold, an account
+    //left with no change dropping out entirely. 0 keeps every change.
+    pub net_threshold: i128,
+    //Denom -> most the tx may burn in total, counting the outputs to the burn addresses. A tx burning
+    //more is rejected with `TxError::BurnBudgetExceeded`.
+    pub max_total_burn: HashMap<String, i128>,
+    //Burn sinks (e.g. an all-zeros address): outputs to them are burnt instead of credited, so the
+    //address gets no balance change and the amount counts in the burnt totals
+    pub burn_addresses: HashSet<String>,
+    //Trusted (e.g. module) accounts that may go negative transiently: their sends skip the
+    //insufficient-balance check, so their final balances may be negative
+    pub allow_negative: HashSet<String>,
+    //Denom -> exact rates the fees of the denom are computed with, instead of the float ones: they
+    //decide which sends are charged, and give the non-issuer shares (each rounded up on its own, even
+    //under `ShareRounding::ExactTotal`) and the issuer fees. The definitions' float rates still feed
+    //the diagnostics (e.g. `TxResult::share_trace`).
+    #[cfg(feature = "rational")]
+    pub rational_rates: HashMap<String, RationalRate>,
+}
+
+//Rounds an exact share up to a whole unit, failing if it doesn't fit an i128
+#[cfg(feature = "rational")]
+fn rational_ceil(share: BigRational) -> Result<i128, TxError> {
+    share.ceil().to_integer().to_i128().ok_or(TxError::Overflow)
+}
+
+//Burn and commission rates as exact fractions
+#[cfg(feature = "rational")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RationalRate {
+    pub burn: BigRational,
+    pub commission: BigRational,
+}
+
+#[cfg(feature = "rational")]
+impl RationalRate {
+    //Rates from (numerator, denominator) pairs, e.g. (8, 100) for 0.08. A zero denominator is rejected.
+    pub fn new(burn: (i128, i128), commission: (i128, i128)) -> Result<RationalRate, TxError> {
+        let ratio = |(numerator, denominator): (i128, i128)| {
+            if denominator == 0 {
+                return Err(TxError::ZeroRateDenominator);
+            }
+            Ok(BigRational::new(
+                BigInt::from(numerator),
+                BigInt::from(denominator),
+            ))
+        };
+        Ok(RationalRate {
+            burn: ratio(burn)?,
+            commission: ratio(commission)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ShareRounding {
+    //Each sender's share is rounded up on its own, as in README example #5
+    #[default]
+    PerShare,
+    //The shares of a denom are reconciled (largest remainder) to add up to the rounded-up total fee,
+    //see `TxData::exact_total_shares`
+    ExactTotal,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AmountSemantics {
+    //The sender pays amount + burn + commission and the recipient gets the full amount
+    #[default]
+    Net,
+    //The sender pays exactly amount and the fees are deducted from what the non-issuer recipients get,
+    //proportionally to their outputs (leftover units from flooring go to the earliest outputs)
+    Gross,
+}
+
+//Detailed output of a processed transaction
+#[derive(Clone, Debug)]
+pub struct TxResult {
+    pub balance_changes: Vec<Balance>,
+    //Every non-issuer sender's burn share before and after rounding, for auditing the burn distribution
+    pub share_trace: Vec<ShareDetail>,
+    //Advisories that do not reject the tx
+    pub warnings: Vec<TxWarning>,
+    pub stats: ProcessingStats,
+    //Denom -> fees collected beyond the floored theoretical amount, due to rounding every share up
+    pub rounding_gain: HashMap<String, i128>,
+    pub fee_shares: Vec<FeeShare>,
+    //Every balance change split by cause, in processing order. Summed per address and denom they give
+    //`balance_changes`.
+    pub tagged_changes: Vec<TaggedChange>,
+    //The tx the result was computed from, and the definitions it was computed with
+    pub multi_send_tx: MultiSend,
+    pub definitions: Vec<DenomDefinition>,
+    //Every mutation of the balance changes map in order, when `TxOptions::audit` is set
+    pub audit: Vec<Mutation>,
+    //Wall-clock time of each processing phase, when `TxOptions::collect_timings` is set
+    pub timings: Option<Timings>,
+    //Denom -> amount the outputs to `TxOptions::burn_addresses` burnt instead of crediting
+    pub burnt_outputs: HashMap<String, i128>,
+}
+
+//Wall-clock time spent in each phase of processing a tx
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Timings {
+    //Tx checks and the address/denom maps, the non-issuer sums excluded
+    pub validation: Duration,
+    //Non-issuer input/output sums
+    pub bc_init: Duration,
+    //Burn & commission of every input, and the commission payouts
+    pub input_loop: Duration,
+    //Output credits, including any gross deductions
+    pub output_loop: Duration,
+    //Building the TxResult from the maps
+    pub collect: Duration,
+}
+
+impl Timings {
+    //Folded stack lines (`process_tx;phase microseconds`), the input format of flamegraph tools
+    pub fn to_folded(&self) -> String {
+        [
+            ("validation", self.validation),
+            ("bc_init", self.bc_init),
+            ("input_loop", self.input_loop),
+            ("output_loop", self.output_loop),
+            ("collect", self.collect),
+        ]
+        .iter()
+        .map(|(phase, time)| format!("process_tx;{} {}\n", phase, time.as_micros()))
+        .collect()
+    }
+}
+
+impl TxResult {
+    //Compares the balance changes against externally computed ones, allowing each (address, denom)
+    //entry to be off by up to `tolerance` units (e.g. when the other side rounds differently).
+    //Returns every entry deviating by more, sorted by address then denom.
+    pub fn verify_result_within(
+        &self,
+        expected: &[Balance],
+        tolerance: i128,
+    ) -> Result<(), Vec<ResultDeviation>> {
+        let amounts = |balances: &[Balance]| {
+            normalize_balances(balances)
+                .into_iter()
+                .flat_map(|balance| {
+                    balance
+                        .coins
+                        .into_iter()
+                        .map(move |coin| ((balance.address.clone(), coin.denom), coin.amount))
+                })
+                .collect::<BTreeMap<(String, String), i128>>()
+        };
+        let actual = amounts(&self.balance_changes);
+        let expected = amounts(expected);
+
+        let deviations = actual
+            .keys()
+            .chain(expected.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|key| {
+                let expected = expected.get(key).copied().unwrap_or(0);
+                let actual = actual.get(key).copied().unwrap_or(0);
+                ((actual - expected).abs() > tolerance).then(|| ResultDeviation {
+                    address: key.0.clone(),
+                    denom: key.1.clone(),
+                    expected,
+                    actual,
+                })
+            })
+            .collect::<Vec<ResultDeviation>>();
+        match deviations.is_empty() {
+            true => Ok(()),
+            false => Err(deviations),
+        }
+    }
+}
+
+//A balance change differing from the expected one by more than the allowed tolerance
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultDeviation {
+    pub address: String,
+    pub denom: String,
+    pub expected: i128,
+    pub actual: i128,
+}
+
+//One balance change of an address on a denom, with its cause
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaggedChange {
+    pub address: String,
+    pub denom: String,
+    pub amount: i128,
+    pub category: ChangeCategory,
+}
+
+//One delta applied to an address's balance change on a denom while processing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mutation {
+    pub address: String,
+    pub denom: String,
+    pub delta: i128,
+    pub reason: ChangeCategory,
+}
+
+//A double-entry pair: `amount` of `denom` leaves `debit_account` and lands in `credit_account`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub debit_account: String,
+    pub credit_account: String,
+    pub denom: String,
+    pub amount: i128,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeCategory {
+    //The amount sent or received
+    Transfer,
+    Burn,
+    //Paid by a sender, or received by the issuer
+    Commission,
+    //A gross mode credit: the amount received less the fees withheld from it
+    Combined,
+}
+
+//Fees charged on one non-issuer send of a denom (or a non-exempt issuer send)
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeShare {
+    pub address: String,
+    pub denom: String,
+    //Credited with the commission
+    pub issuer: String,
+    pub burn: i128,
+    pub commission: i128,
+}
+
+//Cosmos SDK style event, e.g. for indexers
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxEvent {
+    pub event_type: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl TxEvent {
+    fn new(event_type: &str, attributes: &[(&str, String)]) -> TxEvent {
+        TxEvent {
+            event_type: event_type.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.clone()))
+                .collect::<Vec<(String, String)>>(),
+        }
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+//Events for a computed tx: a `transfer` per output coin (with the sender when there is a single input,
+//as cosmos does for MsgMultiSend), then a `burn` and a `commission` per nonzero fee share
+pub fn to_events(detailed: &TxResult) -> Vec<TxEvent> {
+    let mut events = vec![];
+    let single_sender = match detailed.multi_send_tx.inputs.as_slice() {
+        [input] => Some(input.address.clone()),
+        _ => None,
+    };
+
+    for output in detailed.multi_send_tx.outputs.iter() {
+        for coin in output.coins.iter() {
+            let mut attributes = vec![("recipient", output.address.clone())];
+            if let Some(sender) = &single_sender {
+                attributes.push(("sender", sender.clone()));
+            }
+            attributes.push(("amount", coin.amount.to_string()));
+            attributes.push(("denom", coin.denom.clone()));
+            events.push(TxEvent::new("transfer", &attributes));
+        }
+    }
+
+    for share in detailed.fee_shares.iter() {
+        if share.burn != 0 {
+            events.push(TxEvent::new(
+                "burn",
+                &[
+                    ("sender", share.address.clone()),
+                    ("amount", share.burn.to_string()),
+                    ("denom", share.denom.clone()),
+                ],
+            ));
+        }
+        if share.commission != 0 {
+            events.push(TxEvent::new(
+                "commission",
+                &[
+                    ("sender", share.address.clone()),
+                    ("recipient", share.issuer.clone()),
+                    ("amount", share.commission.to_string()),
+                    ("denom", share.denom.clone()),
+                ],
+            ));
+        }
+    }
+
+    events
+}
+
+//Renders balance changes in the cosmos MsgMultiSend shape, `[{"address", "coins": [{"denom", "amount"}]}]`,
+//sorted by address then denom. Amounts are decimal strings, negative for debits.
+pub fn to_cosmos_json(changes: &[Balance]) -> serde_json::Value {
+    serde_json::Value::Array(
+        normalize_balances(changes)
+            .into_iter()
+            .map(|balance| {
+                serde_json::json!({
+                    "address": balance.address,
+                    "coins": balance
+                        .coins
+                        .iter()
+                        .map(|coin| serde_json::json!({
+                            "denom": coin.denom,
+                            "amount": coin.amount.to_string(),
+                        }))
+                        .collect::<Vec<serde_json::Value>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
+//Renders balance changes as `address,denom,amount` rows under a header, sorted by address then denom
+pub fn to_csv(changes: &[Balance]) -> String {
+    let mut csv = String::from("address,denom,amount\n");
+    for balance in normalize_balances(changes).iter() {
+        for coin in balance.coins.iter() {
+            csv.push_str(&format!(
+                "{},{},{}\n",
+                balance.address, coin.denom, coin.amount
+            ));
+        }
+    }
+    csv
+}
+
+//Counters for observability. Burn and commission totals are summed across all denoms.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProcessingStats {
+    pub denoms_processed: usize,
+    pub accounts_touched: usize,
+    pub total_burnt: i128,
+    pub total_commission: i128,
+    //Txs rejected. A single tx only has stats when it is accepted, so this is counted by the batch
+    //APIs, see `apply_batch_skipping_rejected`.
+    pub rejected: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TxWarning {
+    UnusedDefinition { denom: String },
+    //The tx burns `fraction` of the denom's total supply, above `LARGE_BURN_FRACTION`
+    LargeBurnFraction { denom: String, fraction: f64 },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareDetail {
+    pub address: String,
+    pub denom: String,
+    pub raw: f64,
+    pub rounded: i128,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Coin {
+    pub denom: String,
+    pub amount: i128,
+    //Caller data (e.g. an order id) ignored by the math. A recipient's balance change carries the
+    //senders' meta of the denom when the coin passes through unchanged: the recipient is credited
+    //exactly its outputs and every input coin of the denom has that same meta. Fee-adjusted coins,
+    //like the senders' debits, lose it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl Coin {
+    //Lossless decimal text of the amount, sign included
+    pub fn amount_str(&self) -> String {
+        self.amount.to_string()
+    }
+
+    //Parses an amount written by `amount_str`
+    pub fn from_amount_str(denom: &str, amount: &str) -> Result<Coin, TxError> {
+        let parsed = amount.parse::<i128>().map_err(|_| TxError::InvalidAmount {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        })?;
+        Ok(Coin {
+            denom: denom.to_string(),
+            amount: parsed,
+            meta: None,
+        })
+    }
+
+    //Renders the coin in the cosmos SDK wire format, with the amount as a decimal string so i128
+    //values survive JSON parsers limited to 53/64 bit integers
+    pub fn to_cosmos_coin_json(&self) -> String {
+        #[derive(Serialize)]
+        struct CosmosCoin<'a> {
+            denom: &'a str,
+            amount: String,
+        }
+
+        //Serializing a struct of two strings can't fail
+        serde_json::to_string(&CosmosCoin {
+            denom: &self.denom,
+            amount: self.amount_str(),
+        })
+        .unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Balance {
     address: String,
     coins: Vec<Coin>,
 }
 
 // A Denom has a definition (`CoinDefinition`) which contains different attributes related to the denom:
-#[derive(Clone, Debug)]
+//Fields missing from JSON take their `Default` value, so only denom/issuer/rates need to be given
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DenomDefinition {
     // the unique identifier for the token (e.g `core`, `eth`, `usdt`, etc.)
     denom: String,
     // The address that created the token
     issuer: String,
+    //Further authorized issuers of the token. Like the issuer they send it fee free and aren't part
+    //of total_bc, but the commission always goes to `issuer`.
+    issuers: Vec<String>,
     // burn_rate is a number between 0 and 1. If it is above zero, in every transfer,
     // some additional tokens will be burnt on top of the transferred value, from the senders address.
     // The tokens to be burnt are calculated by multiplying the TransferAmount by burn rate, and
@@ -171,6 +1809,173 @@ pub struct DenomDefinition {
     // commission_rate is exactly same as the burn_rate, but the calculated value will be transferred to the
     // issuer's account address instead of being burnt.
     commission_rate: f64,
+    //Whether the issuer skips the burn/commission when sending its own token. A non-exempt fee is
+    //charged on the issuer's full sent amount; the commission is then paid to itself.
+    issuer_exempt_burn: bool,
+    issuer_exempt_commission: bool,
+    //Collectors sharing the commission instead of the issuer, with weights summing to 1.0.
+    //Empty means the issuer gets all of it.
+    commission_split: Vec<(String, f64)>,
+    //Fixed burn and commission charged once per non-issuer sender of the denom, on top of the
+    //rate-based ones
+    flat_burn_fee: i128,
+    flat_commission_fee: i128,
+    //Circulating supply of the token, when known. A tx burning more than
+    //`LARGE_BURN_FRACTION` of it gets a `TxWarning::LargeBurnFraction`.
+    total_supply: Option<i128>,
+}
+
+impl Default for DenomDefinition {
+    fn default() -> Self {
+        DenomDefinition {
+            denom: String::new(),
+            issuer: String::new(),
+            issuers: vec![],
+            burn_rate: 0_f64,
+            commission_rate: 0_f64,
+            issuer_exempt_burn: true,
+            issuer_exempt_commission: true,
+            commission_split: vec![],
+            flat_burn_fee: 0,
+            flat_commission_fee: 0,
+            total_supply: None,
+        }
+    }
+}
+
+impl DenomDefinition {
+    //Whether the address is the issuer or one of the further authorized issuers
+    pub fn is_issuer(&self, address: &str) -> bool {
+        self.issuer == address || self.issuers.iter().any(|issuer| issuer == address)
+    }
+}
+
+//Fluent setters on top of `DenomDefinition::default()`, which has zero rates, an empty denom/issuer
+//and an issuer exempt from both fees
+impl DenomDefinition {
+    pub fn with_denom(mut self, denom: &str) -> Self {
+        self.denom = denom.to_string();
+        self
+    }
+
+    pub fn with_issuer(mut self, issuer: &str) -> Self {
+        self.issuer = issuer.to_string();
+        self
+    }
+
+    pub fn with_issuers(mut self, issuers: Vec<String>) -> Self {
+        self.issuers = issuers;
+        self
+    }
+
+    pub fn with_burn_rate(mut self, burn_rate: f64) -> Self {
+        self.burn_rate = burn_rate;
+        self
+    }
+
+    pub fn with_commission_rate(mut self, commission_rate: f64) -> Self {
+        self.commission_rate = commission_rate;
+        self
+    }
+
+    pub fn with_issuer_exempt_burn(mut self, exempt: bool) -> Self {
+        self.issuer_exempt_burn = exempt;
+        self
+    }
+
+    pub fn with_issuer_exempt_commission(mut self, exempt: bool) -> Self {
+        self.issuer_exempt_commission = exempt;
+        self
+    }
+
+    pub fn with_commission_split(mut self, commission_split: Vec<(String, f64)>) -> Self {
+        self.commission_split = commission_split;
+        self
+    }
+
+    pub fn with_flat_burn_fee(mut self, flat_burn_fee: i128) -> Self {
+        self.flat_burn_fee = flat_burn_fee;
+        self
+    }
+
+    pub fn with_flat_commission_fee(mut self, flat_commission_fee: i128) -> Self {
+        self.flat_commission_fee = flat_commission_fee;
+        self
+    }
+
+    pub fn with_total_supply(mut self, total_supply: i128) -> Self {
+        self.total_supply = Some(total_supply);
+        self
+    }
+}
+
+//Parses a node snapshot holding the bank balances and the asset-ft token params, e.g.
+//`{"balances": [{"address", "coins": [{"denom", "amount"}]}], "tokens": [{"denom", "issuer", "burn_rate",
+//"send_commission_rate"}]}`. Amounts and rates are decimal strings as the node returns them.
+#[cfg(feature = "node-import")]
+pub fn from_node_snapshot(json: &str) -> Result<(Vec<Balance>, Vec<DenomDefinition>), TxError> {
+    #[derive(Deserialize)]
+    struct NodeCoin {
+        denom: String,
+        amount: String,
+    }
+    #[derive(Deserialize)]
+    struct NodeBalance {
+        address: String,
+        coins: Vec<NodeCoin>,
+    }
+    #[derive(Deserialize)]
+    struct NodeToken {
+        denom: String,
+        issuer: String,
+        burn_rate: String,
+        send_commission_rate: String,
+    }
+    #[derive(Deserialize)]
+    struct NodeSnapshot {
+        balances: Vec<NodeBalance>,
+        tokens: Vec<NodeToken>,
+    }
+
+    let invalid = |reason: String| TxError::InvalidSnapshot { reason };
+    let snapshot: NodeSnapshot =
+        serde_json::from_str(json).map_err(|err| invalid(err.to_string()))?;
+
+    let mut balances = vec![];
+    for node_balance in snapshot.balances {
+        let mut coins = vec![];
+        for coin in node_balance.coins {
+            coins.push(
+                Coin::from_amount_str(&coin.denom, &coin.amount).map_err(|_| {
+                    invalid(format!("amount {} of coin {}", coin.amount, coin.denom))
+                })?,
+            );
+        }
+        balances.push(Balance {
+            address: node_balance.address,
+            coins,
+        });
+    }
+
+    let mut definitions = vec![];
+    for token in snapshot.tokens {
+        //Rates are sdk.Dec strings with 18 decimals, which f64 represents closely enough for the rates used
+        let parse_rate = |rate: &str| {
+            rate.parse::<f64>()
+                .map_err(|_| invalid(format!("rate {} of coin {}", rate, token.denom)))
+        };
+        let burn_rate = parse_rate(&token.burn_rate)?;
+        let commission_rate = parse_rate(&token.send_commission_rate)?;
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom(&token.denom)
+                .with_issuer(&token.issuer)
+                .with_burn_rate(burn_rate)
+                .with_commission_rate(commission_rate),
+        );
+    }
+
+    Ok((balances, definitions))
 }
 
 // Implement `calculate_balance_changes` with the following requirements.
@@ -211,356 +2016,6665 @@ fn calculate_balance_changes(
     definitions: Vec<DenomDefinition>,
     multi_send_tx: MultiSend,
 ) -> Result<Vec<Balance>, String> {
+    calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)
+        .map(|result| result.balance_changes)
+        .map_err(|err| err.to_string())
+}
+
+//Same as `calculate_balance_changes`, but returns the detailed result including the share trace
+pub fn calculate_balance_changes_detailed(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<TxResult, TxError> {
+    calculate_balance_changes_with_options(
+        original_balances,
+        definitions,
+        multi_send_tx,
+        &TxOptions::default(),
+    )
+}
+
+//Same as `calculate_balance_changes_detailed`, with the behaviour tuned by `options`
+pub fn calculate_balance_changes_with_options(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    options: &TxOptions,
+) -> Result<TxResult, TxError> {
+    process_tx(original_balances, definitions, multi_send_tx, options, None)
+}
+
+//Same as `calculate_balance_changes_with_options`, but the sufficiency checks query `store`
+//instead of an in-memory list of original balances
+pub fn calculate_balance_changes_with_store(
+    store: &dyn BalanceStore,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    options: &TxOptions,
+) -> Result<TxResult, TxError> {
+    process_tx(vec![], definitions, multi_send_tx, options, Some(store))
+}
+
+//Runs every check `calculate_balance_changes` does (including the balance checks) without building
+//a result, for callers that only need to know whether the tx would be accepted
+pub fn validate_transaction(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<(), TxError> {
+    let options = TxOptions::default();
+    let tx_data = prepare_tx(original_balances, definitions, multi_send_tx, &options)?;
+    apply_tx(tx_data, &options, None).map(|_| ())
+}
+
+//Same as `calculate_balance_changes_detailed`, but returns the address -> denom -> change map as built
+//during processing instead of flattening it into balances
+pub fn calculate_balance_changes_map(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<HashMap<String, HashMap<String, i128>>, TxError> {
+    let options = TxOptions::default();
+    let tx_data = prepare_tx(original_balances, definitions, multi_send_tx, &options)?;
+    Ok(apply_tx(tx_data, &options, None)?.coin_balance_changes_map)
+}
+
+//Processes only the coins whose denom is in `denoms`, as if the others were not in the tx at all
+//(they are neither validated nor charged). Lets operators migrate denom by denom.
+pub fn calculate_balance_changes_filtered(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    denoms: &HashSet<String>,
+) -> Result<Vec<Balance>, TxError> {
+    let filter = |balances: Vec<Balance>| {
+        balances
+            .into_iter()
+            .map(|balance| Balance {
+                address: balance.address,
+                coins: balance
+                    .coins
+                    .into_iter()
+                    .filter(|coin| denoms.contains(&coin.denom))
+                    .collect::<Vec<Coin>>(),
+            })
+            .filter(|balance| !balance.coins.is_empty())
+            .collect::<Vec<Balance>>()
+    };
+    let multi_send_tx = MultiSend {
+        inputs: filter(multi_send_tx.inputs),
+        outputs: filter(multi_send_tx.outputs),
+        memo: multi_send_tx.memo,
+    };
+
+    calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)
+        .map(|result| result.balance_changes)
+}
+
+//Runs the tx against `store`, or against the original balances when no store is given
+fn process_tx(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    options: &TxOptions,
+    store: Option<&dyn BalanceStore>,
+) -> Result<TxResult, TxError> {
+    let tx_data = prepare_tx(original_balances, definitions, multi_send_tx, options)?;
+    run_tx(tx_data, options, store)
+}
+
+//Validates the tx against the options and builds its TxData, ready to be run
+fn prepare_tx(
+    mut original_balances: Vec<Balance>,
+    mut definitions: Vec<DenomDefinition>,
+    mut multi_send_tx: MultiSend,
+    options: &TxOptions,
+) -> Result<TxData, TxError> {
+    if options.case_insensitive_denoms {
+        for coin in original_balances
+            .iter_mut()
+            .chain(multi_send_tx.inputs.iter_mut())
+            .chain(multi_send_tx.outputs.iter_mut())
+            .flat_map(|balance| balance.coins.iter_mut())
+        {
+            coin.denom = normalize_denom(&coin.denom);
+        }
+        for definition in definitions.iter_mut() {
+            definition.denom = normalize_denom(&definition.denom);
+        }
+    }
+
     //First validate the transaction
-    multi_send_tx.validate_multi_send_tx()?;
+    let start = Instant::now();
+    validate_entry_limits(&multi_send_tx, options)?;
+    validate_transfer_caps(&multi_send_tx, options)?;
+    let mut tx_data = TxData::prepared(multi_send_tx, original_balances, definitions)?;
+
+    //Gross fees come out of the outputs, so above 100% a recipient would be debited
+    if options.reject_fee_over_100_percent || options.amount_semantics == AmountSemantics::Gross {
+        tx_data.validate_fee_rates()?;
+    }
 
-    let mut tx_data = TxData::new(multi_send_tx, original_balances, definitions);
+    if options.strict_balance_entries {
+        tx_data.validate_balance_entries()?;
+    }
+
+    #[cfg(feature = "rational")]
+    if !options.rational_rates.is_empty() {
+        tx_data.recheck_fee_free(&options.rational_rates)?;
+    }
+
+    if options.collect_timings {
+        tx_data.timings = Some(Timings {
+            validation: start.elapsed().saturating_sub(tx_data.bc_init_time),
+            bc_init: tx_data.bc_init_time,
+            ..Timings::default()
+        });
+    }
+    Ok(tx_data)
+}
+
+//Computes the balance changes of a prepared tx, checking the senders' balances along the way
+fn run_tx(
+    tx_data: TxData,
+    options: &TxOptions,
+    store: Option<&dyn BalanceStore>,
+) -> Result<TxResult, TxError> {
+    let tx_data = apply_tx(tx_data, options, store)?;
+    tx_data.validate_burn_budgets(&options.max_total_burn)?;
 
-    //Initialize the maps for denoms & balances
-    tx_data.initialize_balances_map();
-    tx_data.initialize_definitions_map();
+    let definitions = match options.exclude_issuers_from_output {
+        true => tx_data.denom_definitions_map.clone(),
+        false => HashMap::new(),
+    };
+    let start = Instant::now();
+    let mut result = tx_data.into_result();
+    if let Some(timings) = result.timings.as_mut() {
+        timings.collect = start.elapsed();
+    }
+    if options.exclude_issuers_from_output {
+        for balance in result.balance_changes.iter_mut() {
+            balance.coins.retain(|coin| {
+                !definitions
+                    .get(&coin.denom)
+                    .is_some_and(|definition| definition.is_issuer(&balance.address))
+            });
+        }
+        result
+            .balance_changes
+            .retain(|balance| !balance.coins.is_empty());
+    }
+    if options.net_threshold > 0 {
+        for balance in result.balance_changes.iter_mut() {
+            balance
+                .coins
+                .retain(|coin| coin.amount.abs() > options.net_threshold);
+        }
+        result
+            .balance_changes
+            .retain(|balance| !balance.coins.is_empty());
+    }
+
+    //Return the processed balances along with the trace
+    Ok(result)
+}
 
-    //Populate the commission & burn rate data
-    tx_data.initialize_bc_data();
+//Runs the input and output loops of a prepared tx, filling its balance changes map and fee records
+fn apply_tx(
+    mut tx_data: TxData,
+    options: &TxOptions,
+    store: Option<&dyn BalanceStore>,
+) -> Result<TxData, TxError> {
+    tx_data.audit = options.audit.then(Vec::new);
+    let start = Instant::now();
+    if tx_data.fee_free {
+        return apply_fee_free_tx(tx_data, options, store, start);
+    }
+    let store = store.unwrap_or(&tx_data.balances as &dyn BalanceStore);
 
     //Process the inputs accounting for burn/commision rate on sender/issuer
     //Account changes on the inputs
-    for input in tx_data.multi_send_tx.inputs.iter() {
-        for (idx, coin) in input.coins.iter().enumerate() {
+    let exact_shares = match options.share_rounding {
+        ShareRounding::PerShare => None,
+        ShareRounding::ExactTotal => Some((
+            tx_data.exact_total_shares(|definition| definition.burn_rate),
+            tx_data.exact_total_shares(|definition| definition.commission_rate),
+        )),
+    };
+    //The denoms with exact rates take their exact shares over the reconciled float ones
+    #[cfg(feature = "rational")]
+    let exact_shares = match options.rational_rates.is_empty() {
+        true => exact_shares,
+        false => {
+            let (mut burns, mut commissions) = exact_shares.unwrap_or_default();
+            burns.extend(tx_data.rational_shares(&options.rational_rates, |rate| &rate.burn)?);
+            commissions
+                .extend(tx_data.rational_shares(&options.rational_rates, |rate| &rate.commission)?);
+            Some((burns, commissions))
+        }
+    };
+    //(sender, denom) pairs that already paid the denom's flat fees
+    let mut flat_fees_charged: HashSet<(&str, &str)> = HashSet::new();
+    let mut debited = SenderDebits::new();
+    //Denom -> commission collected for denoms whose commission is split between collectors
+    let mut split_commissions: HashMap<String, i128> = HashMap::new();
+    for (input_idx, input) in tx_data.multi_send_tx.inputs.iter().enumerate() {
+        for (coin_idx, coin) in input.coins.iter().enumerate() {
             if let Some(definition) = tx_data.denom_definitions_map.get(&coin.denom) {
                 //Only decrease balance by the burn/commission if the address is not the issuer.
-                if input.address != definition.issuer {
-                    //Get the non_issuer_input_sum & non_issuer_output_sum for the denom
-                    let non_issuer_input_sum =
-                        tx_data.non_issuer_input_sum_map.get(&coin.denom).unwrap(); //Unwrap since all should be copacetic in the map
-                    let non_issuer_output_sum =
-                        tx_data.non_issuer_output_sum_map.get(&coin.denom).unwrap(); //Here as well
-
-                    //Calculate the total burn/commission
-                    let total_bc = min(*non_issuer_input_sum, *non_issuer_output_sum);
+                if !definition.is_issuer(&input.address) {
+                    //Get the total burn/commission and the non_issuer_input_sum for the denom.
+                    //The input sum is always present here since this address is a non-issuer sender, but
+                    //the output sum is absent when every output goes to the issuer, so both default to 0.
+                    let (total_bc, non_issuer_input_sum) = tx_data.bc_base(&coin.denom);
                     //Calculate the commission and burn amount
-                    let burn_amount = evaluate_rate(
+                    let raw_burn = raw_share(
                         coin.amount,
                         definition.burn_rate,
                         total_bc,
-                        *non_issuer_input_sum,
-                    );
-                    let commission_amount = evaluate_rate(
-                        coin.amount,
-                        definition.commission_rate,
-                        total_bc,
-                        *non_issuer_input_sum,
+                        non_issuer_input_sum,
                     );
-                    //Ensure the input address has sufficient balance to cover the amount + burn + commision
-                    //Unwraping is fine here, as we know the address exists in the map
-                    if let Some(_coin) = tx_data
-                        .balances_map
-                        .get(&input.address)
-                        .unwrap()
-                        .coins
-                        .get(idx)
+                    //Precomputed shares cover every input coin in ExactTotal mode, but only the
+                    //denoms with exact rates in rational mode
+                    let precomputed = exact_shares.as_ref().and_then(|(burns, commissions)| {
+                        let key = (input_idx, coin_idx);
+                        Some((*burns.get(&key)?, *commissions.get(&key)?))
+                    });
+                    let (burn_amount, commission_amount) = match precomputed {
+                        Some(shares) => shares,
+                        None => (
+                            match options.min_burn_floor {
+                                Some(floor) if raw_burn < floor => 0,
+                                _ => roundup_within(raw_burn, options.ceil_epsilon),
+                            },
+                            roundup_within(
+                                raw_share(
+                                    coin.amount,
+                                    definition.commission_rate,
+                                    total_bc,
+                                    non_issuer_input_sum,
+                                ),
+                                options.ceil_epsilon,
+                            ),
+                        ),
+                    };
+                    tx_data.share_trace.push(ShareDetail {
+                        address: input.address.clone(),
+                        denom: coin.denom.clone(),
+                        raw: raw_burn,
+                        rounded: burn_amount,
+                    });
+                    //The flat fees are charged once per sender and denom, on its first send of the denom
+                    let (burn_amount, commission_amount) = match flat_fees_charged
+                        .insert((input.address.as_str(), coin.denom.as_str()))
                     {
-                        if _coin.amount < coin.amount + burn_amount + commission_amount {
-                            return Err(format!(
-                                "Inssuficient wallet balance on {} for coin {}",
-                                input.address, coin.denom
-                            ));
-                        }
-                    } else {
-                        return Err(format!(
-                            "Inssuficient wallet balance on {} for coin {}",
-                            input.address, coin.denom
-                        ));
-                    }
+                        true => (
+                            burn_amount
+                                .checked_add(definition.flat_burn_fee)
+                                .ok_or(TxError::Overflow)?,
+                            commission_amount
+                                .checked_add(definition.flat_commission_fee)
+                                .ok_or(TxError::Overflow)?,
+                        ),
+                        false => (burn_amount, commission_amount),
+                    };
+                    check_fees_nonnegative(burn_amount, commission_amount, &input.address, coin)?;
+                    tx_data.fee_shares.push(FeeShare {
+                        address: input.address.clone(),
+                        denom: coin.denom.clone(),
+                        issuer: definition.issuer.clone(),
+                        burn: burn_amount,
+                        commission: commission_amount,
+                    });
 
-                    //Update the senders balance in the coin_balance_changes hashmap
-                    if let Some(coin_map) = tx_data.coin_balance_changes_map.get_mut(&input.address)
-                    {
-                        if let Some(coin_amount) = coin_map.get_mut(&coin.denom) {
-                            *coin_amount += -(burn_amount + commission_amount + coin.amount)
-                        } else {
-                            coin_map.insert(
-                                coin.denom.clone(),
-                                -(burn_amount + commission_amount + coin.amount),
-                            );
-                        }
-                    } else {
-                        let mut coin_map = HashMap::new();
-                        coin_map.insert(
-                            coin.denom.clone(),
-                            -(burn_amount + commission_amount + coin.amount),
-                        );
+                    accumulate(
                         tx_data
-                            .coin_balance_changes_map
-                            .insert(input.address.clone(), coin_map);
-                    }
+                            .total_burn_map
+                            .entry(coin.denom.clone())
+                            .or_insert(0),
+                        burn_amount,
+                    )?;
+                    accumulate(
+                        tx_data
+                            .total_commission_map
+                            .entry(coin.denom.clone())
+                            .or_insert(0),
+                        commission_amount,
+                    )?;
 
-                    //Update the issuers balance in the coin_balance_changes hashmap
-                    if let Some(coin_map) =
-                        tx_data.coin_balance_changes_map.get_mut(&definition.issuer)
-                    {
-                        if let Some(coin_amount) = coin_map.get_mut(&coin.denom) {
-                            *coin_amount += commission_amount
-                        } else if commission_amount != 0 {
-                            coin_map.insert(coin.denom.clone(), commission_amount);
-                        }
+                    //In gross mode the fees come out of the outputs instead of the sender
+                    let fees = match options.amount_semantics {
+                        AmountSemantics::Net => burn_amount
+                            .checked_add(commission_amount)
+                            .ok_or(TxError::Overflow)?,
+                        AmountSemantics::Gross => 0,
+                    };
+
+                    //Ensure the input address has sufficient balance to cover the amount + burn + commision
+                    //of all its sends of the denom so far
+                    check_sufficient_balance(
+                        &mut debited,
+                        store,
+                        options,
+                        &input.address,
+                        coin,
+                        fees,
+                    )?;
+
+                    //Update the senders balance in the coin_balance_changes hashmap, the fees being
+                    //applied separately so an audit shows each of them
+                    let (burn_debit, commission_debit) = match options.amount_semantics {
+                        AmountSemantics::Net => (burn_amount, commission_amount),
+                        AmountSemantics::Gross => (0, 0),
+                    };
+                    let fee_debits = [
+                        (-coin.amount, ChangeCategory::Transfer),
+                        (-burn_debit, ChangeCategory::Burn),
+                        (-commission_debit, ChangeCategory::Commission),
+                    ];
+                    for (delta, reason) in fee_debits {
+                        add_balance_change(
+                            &mut tx_data.coin_balance_changes_map,
+                            &mut tx_data.audit,
+                            &input.address,
+                            &coin.denom,
+                            delta,
+                            reason,
+                        )?;
+                    }
+                    let tagged = &mut tx_data.tagged_changes;
+                    tag_change(
+                        tagged,
+                        &input.address,
+                        &coin.denom,
+                        -coin.amount,
+                        ChangeCategory::Transfer,
+                    );
+                    if options.amount_semantics == AmountSemantics::Net {
+                        tag_change(
+                            tagged,
+                            &input.address,
+                            &coin.denom,
+                            -burn_amount,
+                            ChangeCategory::Burn,
+                        );
+                        tag_change(
+                            tagged,
+                            &input.address,
+                            &coin.denom,
+                            -commission_amount,
+                            ChangeCategory::Commission,
+                        );
+                    }
+
+                    //Update the issuers balance in the coin_balance_changes hashmap.
+                    //The issuer may also be sending this denom, so this accumulates onto its own debit.
+                    if !definition.commission_split.is_empty() {
+                        accumulate(
+                            split_commissions.entry(coin.denom.clone()).or_insert(0),
+                            commission_amount,
+                        )?;
                     } else if commission_amount != 0 {
-                        let mut coin_map = HashMap::new();
-                        coin_map.insert(coin.denom.clone(), commission_amount);
-                        tx_data
-                            .coin_balance_changes_map
-                            .insert(definition.issuer.clone(), coin_map);
+                        add_balance_change(
+                            &mut tx_data.coin_balance_changes_map,
+                            &mut tx_data.audit,
+                            &definition.issuer,
+                            &coin.denom,
+                            commission_amount,
+                            ChangeCategory::Commission,
+                        )?;
+                        tag_change(
+                            &mut tx_data.tagged_changes,
+                            &definition.issuer,
+                            &coin.denom,
+                            commission_amount,
+                            ChangeCategory::Commission,
+                        );
                     }
                 } else {
+                    //The issuer's send isn't part of total_bc, so a non-exempt fee is charged on its full amount
+                    let issuer_fee = |exempt: bool, rate: f64| match exempt {
+                        true => 0,
+                        false => roundup_within(coin.amount as f64 * rate, options.ceil_epsilon),
+                    };
+                    let burn_amount =
+                        issuer_fee(definition.issuer_exempt_burn, definition.burn_rate);
+                    let commission_amount = issuer_fee(
+                        definition.issuer_exempt_commission,
+                        definition.commission_rate,
+                    );
+                    #[cfg(feature = "rational")]
+                    let (burn_amount, commission_amount) = match options
+                        .rational_rates
+                        .get(&coin.denom)
+                    {
+                        Some(rates) => {
+                            let exact_fee = |exempt: bool, rate: &BigRational| match exempt {
+                                true => Ok(0),
+                                false => rational_ceil(rate * BigInt::from(coin.amount)),
+                            };
+                            (
+                                exact_fee(definition.issuer_exempt_burn, &rates.burn)?,
+                                exact_fee(definition.issuer_exempt_commission, &rates.commission)?,
+                            )
+                        }
+                        None => (burn_amount, commission_amount),
+                    };
+                    check_fees_nonnegative(burn_amount, commission_amount, &input.address, coin)?;
+                    if burn_amount != 0 || commission_amount != 0 {
+                        tx_data.fee_shares.push(FeeShare {
+                            address: input.address.clone(),
+                            denom: coin.denom.clone(),
+                            issuer: definition.issuer.clone(),
+                            burn: burn_amount,
+                            commission: commission_amount,
+                        });
+                        accumulate(
+                            tx_data
+                                .total_burn_map
+                                .entry(coin.denom.clone())
+                                .or_insert(0),
+                            burn_amount,
+                        )?;
+                        accumulate(
+                            tx_data
+                                .total_commission_map
+                                .entry(coin.denom.clone())
+                                .or_insert(0),
+                            commission_amount,
+                        )?;
+                    }
+
                     //Update the issuers balance in the coin_balance_changes hashmap
                     //If the issuer is sending the tokens simply decrease the balance by the amount spent
-                    if let Some(coin_map) = tx_data.coin_balance_changes_map.get_mut(&input.address)
-                    {
-                        if let Some(coin_amount) = coin_map.get_mut(&coin.denom) {
-                            *coin_amount -= coin.amount
-                        } else {
-                            coin_map.insert(coin.denom.clone(), coin.amount);
+                    //plus any burn; a commission is paid back to itself so it nets out, unless the
+                    //commission is split between collectors or the sender is a co-issuer
+                    let kept_commission = match (
+                        definition.commission_split.is_empty(),
+                        input.address == definition.issuer,
+                    ) {
+                        (true, true) => commission_amount,
+                        (true, false) => {
+                            if commission_amount != 0 {
+                                add_balance_change(
+                                    &mut tx_data.coin_balance_changes_map,
+                                    &mut tx_data.audit,
+                                    &definition.issuer,
+                                    &coin.denom,
+                                    commission_amount,
+                                    ChangeCategory::Commission,
+                                )?;
+                                tag_change(
+                                    &mut tx_data.tagged_changes,
+                                    &definition.issuer,
+                                    &coin.denom,
+                                    commission_amount,
+                                    ChangeCategory::Commission,
+                                );
+                            }
+                            0
                         }
-                    } else {
-                        let mut coin_map = HashMap::new();
-                        coin_map.insert(coin.denom.clone(), -coin.amount);
-                        tx_data
-                            .coin_balance_changes_map
-                            .insert(input.address.clone(), coin_map);
+                        (false, _) => {
+                            accumulate(
+                                split_commissions.entry(coin.denom.clone()).or_insert(0),
+                                commission_amount,
+                            )?;
+                            0
+                        }
+                    };
+                    let issuer_debits = [
+                        (-coin.amount, ChangeCategory::Transfer),
+                        (-burn_amount, ChangeCategory::Burn),
+                        (
+                            kept_commission
+                                .checked_sub(commission_amount)
+                                .ok_or(TxError::Overflow)?,
+                            ChangeCategory::Commission,
+                        ),
+                    ];
+                    for (delta, reason) in issuer_debits {
+                        add_balance_change(
+                            &mut tx_data.coin_balance_changes_map,
+                            &mut tx_data.audit,
+                            &input.address,
+                            &coin.denom,
+                            delta,
+                            reason,
+                        )?;
+                    }
+                    let tagged = &mut tx_data.tagged_changes;
+                    tag_change(
+                        tagged,
+                        &input.address,
+                        &coin.denom,
+                        -coin.amount,
+                        ChangeCategory::Transfer,
+                    );
+                    tag_change(
+                        tagged,
+                        &input.address,
+                        &coin.denom,
+                        -burn_amount,
+                        ChangeCategory::Burn,
+                    );
+                    tag_change(
+                        tagged,
+                        &input.address,
+                        &coin.denom,
+                        -commission_amount,
+                        ChangeCategory::Commission,
+                    );
+                    tag_change(
+                        tagged,
+                        &input.address,
+                        &coin.denom,
+                        kept_commission,
+                        ChangeCategory::Commission,
+                    );
+                }
+            }
+        }
+    }
+
+    //Pay out the split commissions, per denom so the collectors' parts add up exactly to the total
+    let mut split_commissions = split_commissions
+        .into_iter()
+        .collect::<Vec<(String, i128)>>();
+    split_commissions.sort();
+    for (denom, total) in split_commissions {
+        let split = &tx_data.denom_definitions_map[&denom].commission_split;
+        for (collector, amount) in split_by_weights(total, split) {
+            add_balance_change(
+                &mut tx_data.coin_balance_changes_map,
+                &mut tx_data.audit,
+                &collector,
+                &denom,
+                amount,
+                ChangeCategory::Commission,
+            )?;
+            tag_change(
+                &mut tx_data.tagged_changes,
+                &collector,
+                &denom,
+                amount,
+                ChangeCategory::Commission,
+            );
+        }
+    }
+
+    let output_start = Instant::now();
+    if let Some(timings) = tx_data.timings.as_mut() {
+        timings.input_loop = start.elapsed();
+    }
+
+    //Amounts withheld from each output coin, keyed by (output index, coin index)
+    let deductions = match options.amount_semantics {
+        AmountSemantics::Net => HashMap::new(),
+        AmountSemantics::Gross => tx_data.gross_output_deductions()?,
+    };
+    apply_outputs(&mut tx_data, options, &deductions)?;
+    if let Some(timings) = tx_data.timings.as_mut() {
+        timings.output_loop = output_start.elapsed();
+    }
+
+    Ok(tx_data)
+}
+
+//Plain add/subtract accounting for a tx where no send is charged a fee. Records the same zero fee
+//shares as the general path so both produce identical results.
+fn apply_fee_free_tx(
+    mut tx_data: TxData,
+    options: &TxOptions,
+    store: Option<&dyn BalanceStore>,
+    start: Instant,
+) -> Result<TxData, TxError> {
+    let store = store.unwrap_or(&tx_data.balances as &dyn BalanceStore);
+    let mut debited = SenderDebits::new();
+    for input in tx_data.multi_send_tx.inputs.iter() {
+        for coin in input.coins.iter() {
+            if let Some(definition) = tx_data.denom_definitions_map.get(&coin.denom) {
+                if !definition.is_issuer(&input.address) {
+                    tx_data.share_trace.push(ShareDetail {
+                        address: input.address.clone(),
+                        denom: coin.denom.clone(),
+                        raw: 0_f64,
+                        rounded: 0,
+                    });
+                    tx_data.fee_shares.push(FeeShare {
+                        address: input.address.clone(),
+                        denom: coin.denom.clone(),
+                        issuer: definition.issuer.clone(),
+                        burn: 0,
+                        commission: 0,
+                    });
+                    tx_data
+                        .total_burn_map
+                        .entry(coin.denom.clone())
+                        .or_insert(0);
+                    tx_data
+                        .total_commission_map
+                        .entry(coin.denom.clone())
+                        .or_insert(0);
+
+                    check_sufficient_balance(
+                        &mut debited,
+                        store,
+                        options,
+                        &input.address,
+                        coin,
+                        0,
+                    )?;
+                }
+                add_balance_change(
+                    &mut tx_data.coin_balance_changes_map,
+                    &mut tx_data.audit,
+                    &input.address,
+                    &coin.denom,
+                    -coin.amount,
+                    ChangeCategory::Transfer,
+                )?;
+                tag_change(
+                    &mut tx_data.tagged_changes,
+                    &input.address,
+                    &coin.denom,
+                    -coin.amount,
+                    ChangeCategory::Transfer,
+                );
+            }
+        }
+    }
+
+    let output_start = Instant::now();
+    if let Some(timings) = tx_data.timings.as_mut() {
+        timings.input_loop = start.elapsed();
+    }
+
+    //Nothing is withheld from the outputs, whatever the amount semantics
+    apply_outputs(&mut tx_data, options, &HashMap::new())?;
+    if let Some(timings) = tx_data.timings.as_mut() {
+        timings.output_loop = output_start.elapsed();
+    }
+    Ok(tx_data)
+}
+
+//Credits the output amounts, less the amounts withheld keyed by (output index, coin index)
+fn apply_outputs(
+    tx_data: &mut TxData,
+    options: &TxOptions,
+    deductions: &HashMap<(usize, usize), i128>,
+) -> Result<(), TxError> {
+    for (output_idx, output) in tx_data.multi_send_tx.outputs.iter().enumerate() {
+        for (coin_idx, coin) in output.coins.iter().enumerate() {
+            //A zero output moves nothing, so it must not create an entry for the recipient
+            if coin.amount == 0 {
+                continue;
+            }
+            let deduction = deductions
+                .get(&(output_idx, coin_idx))
+                .copied()
+                .unwrap_or(0);
+            let credit = coin
+                .amount
+                .checked_sub(deduction)
+                .ok_or(TxError::Overflow)?;
+            if let Some(minimum) = options.min_transfer.get(&coin.denom) {
+                if credit < *minimum {
+                    return Err(TxError::DustOutput {
+                        denom: coin.denom.clone(),
+                        amount: credit,
+                    });
+                }
+            }
+            if options.burn_addresses.contains(&output.address) {
+                accumulate(
+                    tx_data
+                        .burnt_outputs_map
+                        .entry(coin.denom.clone())
+                        .or_insert(0),
+                    credit,
+                )?;
+                continue;
+            }
+            //A gross credit is the transfer net of the fees withheld from it
+            let category = match deduction {
+                0 => ChangeCategory::Transfer,
+                _ => ChangeCategory::Combined,
+            };
+            //Update the recipients balance in the coin_balance_changes hashmap
+            add_balance_change(
+                &mut tx_data.coin_balance_changes_map,
+                &mut tx_data.audit,
+                &output.address,
+                &coin.denom,
+                credit,
+                category,
+            )?;
+            tag_change(
+                &mut tx_data.tagged_changes,
+                &output.address,
+                &coin.denom,
+                credit,
+                category,
+            );
+        }
+    }
+    Ok(())
+}
+
+//A memoized tx result: the tx is validated and computed once, with the default options, and
+//`compute` hands out the balance changes as often as needed (e.g. simulation then commit) without
+//re-running the validation. The balance checks need the full computation, so nothing cheaper than
+//the result itself is worth caching.
+pub struct ValidatedTx {
+    balance_changes: Vec<Balance>,
+}
+
+impl ValidatedTx {
+    pub fn new(
+        original_balances: Vec<Balance>,
+        definitions: Vec<DenomDefinition>,
+        multi_send_tx: MultiSend,
+    ) -> Result<ValidatedTx, TxError> {
+        let tx_data = TxData::prepared(multi_send_tx, original_balances, definitions)?;
+        let result = run_tx(tx_data, &TxOptions::default(), None)?;
+        Ok(ValidatedTx {
+            balance_changes: result.balance_changes,
+        })
+    }
+
+    pub fn compute(&self) -> Vec<Balance> {
+        self.balance_changes.clone()
+    }
+}
+
+//A tx being assembled entry by entry, tracking the running balance changes for live display.
+//The fees depend on the non-issuer sums over the whole tx, so the running changes only move the
+//amounts sent and received: burn and commission are only charged on `finalize`.
+pub struct LiveTx {
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    running: Balances,
+}
+
+impl LiveTx {
+    pub fn new(original_balances: Vec<Balance>, definitions: Vec<DenomDefinition>) -> LiveTx {
+        LiveTx {
+            original_balances,
+            definitions,
+            multi_send_tx: MultiSend {
+                inputs: vec![],
+                outputs: vec![],
+                memo: None,
+            },
+            running: Balances::default(),
+        }
+    }
+
+    //Adds an input, returning the debits it adds to the running changes
+    pub fn add_input(&mut self, input: Balance) -> Result<Vec<Balance>, TxError> {
+        let effect = Balance {
+            address: input.address.clone(),
+            coins: input
+                .coins
+                .iter()
+                .map(|coin| Coin {
+                    denom: coin.denom.clone(),
+                    amount: -coin.amount,
+                    meta: None,
+                })
+                .collect::<Vec<Coin>>(),
+        };
+        let effect = self.apply(effect)?;
+        self.multi_send_tx.inputs.push(input);
+        Ok(effect)
+    }
+
+    //Adds an output, returning the credits it adds to the running changes
+    pub fn add_output(&mut self, output: Balance) -> Result<Vec<Balance>, TxError> {
+        let effect = self.apply(output.clone())?;
+        self.multi_send_tx.outputs.push(output);
+        Ok(effect)
+    }
+
+    //Balance changes of the entries added so far, without any fee
+    pub fn running_changes(&self) -> Vec<Balance> {
+        self.running.clone().into_vec()
+    }
+
+    //Validates the assembled tx and computes its changes, fees included
+    pub fn finalize(self) -> Result<TxResult, TxError> {
+        calculate_balance_changes_detailed(
+            self.original_balances,
+            self.definitions,
+            self.multi_send_tx,
+        )
+    }
+
+    //Entries whose changes overflow are rejected, leaving the running changes untouched
+    fn apply(&mut self, effect: Balance) -> Result<Vec<Balance>, TxError> {
+        let mut coins = self
+            .running
+            .0
+            .get(&effect.address)
+            .cloned()
+            .unwrap_or_default();
+        for coin in effect.coins.iter() {
+            accumulate(coins.entry(coin.denom.clone()).or_insert(0), coin.amount)?;
+        }
+        self.running.0.insert(effect.address.clone(), coins);
+        Ok(normalize_balances(&[effect]))
+    }
+}
+
+//Every address the tx touches: senders, recipients and the issuers earning a nonzero commission.
+//Lets a settlement engine lock the accounts before processing.
+pub fn affected_addresses(
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+) -> Result<BTreeSet<String>, TxError> {
+    let tx_data = TxData::unvalidated(multi_send_tx, definitions)?;
+
+    let mut addresses = BTreeSet::new();
+    for input in tx_data.multi_send_tx.inputs.iter() {
+        addresses.insert(input.address.clone());
+        for coin in input.coins.iter() {
+            if let Some(definition) = tx_data.denom_definitions_map.get(&coin.denom) {
+                if !definition.is_issuer(&input.address) {
+                    let (total_bc, non_issuer_input_sum) = tx_data.bc_base(&coin.denom);
+                    let commission_amount = evaluate_rate(
+                        coin.amount,
+                        definition.commission_rate,
+                        total_bc,
+                        non_issuer_input_sum,
+                    ) + definition.flat_commission_fee;
+                    if commission_amount != 0 && definition.commission_split.is_empty() {
+                        addresses.insert(definition.issuer.clone());
+                    } else if commission_amount != 0 {
+                        for (collector, _) in definition.commission_split.iter() {
+                            addresses.insert(collector.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for output in tx_data.multi_send_tx.outputs.iter() {
+        addresses.insert(output.address.clone());
+    }
+
+    Ok(addresses)
+}
+
+//Total burnt on a denom for each candidate burn_rate, everything else held fixed. Rates at which the
+//tx is rejected (e.g. the senders can no longer cover the burn) are left out of the result.
+pub fn sweep_rates(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    denom: &str,
+    burn_rates: &[f64],
+) -> Vec<(f64, i128)> {
+    burn_rates
+        .iter()
+        .filter_map(|burn_rate| {
+            let definitions = definitions
+                .iter()
+                .map(|definition| match definition.denom == denom {
+                    true => definition.clone().with_burn_rate(*burn_rate),
+                    false => definition.clone(),
+                })
+                .collect::<Vec<DenomDefinition>>();
+            let result = calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions,
+                multi_send_tx.clone(),
+            )
+            .ok()?;
+            let total_burnt = result
+                .fee_shares
+                .iter()
+                .filter(|share| share.denom == denom)
+                .map(|share| share.burn)
+                .sum::<i128>();
+            Some((*burn_rate, total_burnt))
+        })
+        .collect()
+}
+
+//Recomputes the tx and checks its total burn on a denom against a reported value (e.g. from a chain),
+//returning a `TxError::BurnMismatch` when the two diverge
+pub fn assert_burn_matches(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    denom: &str,
+    expected_burn: i128,
+) -> Result<(), TxError> {
+    let result = calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)?;
+    let computed = result
+        .fee_shares
+        .iter()
+        .filter(|share| share.denom == denom)
+        .map(|share| share.burn)
+        .sum::<i128>();
+    if computed != expected_burn {
+        return Err(TxError::BurnMismatch {
+            denom: denom.to_string(),
+            expected: expected_burn,
+            computed,
+        });
+    }
+    Ok(())
+}
+
+//Sum of the stated outputs per (address, denom). In the default net mode fees are charged to the
+//senders, so this is exactly what every non-issuer recipient is credited.
+pub fn recipient_credits(multi_send_tx: &MultiSend) -> HashMap<(String, String), i128> {
+    let mut credits = HashMap::new();
+    for output in multi_send_tx.outputs.iter() {
+        for coin in output.coins.iter() {
+            *credits
+                .entry((output.address.clone(), coin.denom.clone()))
+                .or_insert(0) += coin.amount;
+        }
+    }
+    credits
+}
+
+//Checks that the non-issuer recipients of a denom were credited exactly non_issuer_output_sum, as
+//they get the full output amounts when the fees are charged to the senders (net amount semantics)
+pub fn verify_output_sum(detailed: &TxResult, denom: &str) -> Result<(), TxError> {
+    let tx_data = TxData::unvalidated(&detailed.multi_send_tx, &detailed.definitions)?;
+    let expected = tx_data
+        .non_issuer_output_sum_map
+        .get(denom)
+        .copied()
+        .unwrap_or(0);
+    let credited = detailed
+        .tagged_changes
+        .iter()
+        .filter(|tagged| {
+            tagged.denom == denom
+                && tagged.amount > 0
+                && matches!(
+                    tagged.category,
+                    ChangeCategory::Transfer | ChangeCategory::Combined
+                )
+                && !tx_data
+                    .denom_definitions_map
+                    .get(denom)
+                    .is_some_and(|definition| definition.is_issuer(&tagged.address))
+        })
+        .map(|tagged| tagged.amount)
+        .sum::<i128>();
+
+    if credited != expected {
+        return Err(TxError::OutputSumMismatch {
+            denom: denom.to_string(),
+            expected,
+            credited,
+        });
+    }
+    Ok(())
+}
+
+//Checks that the commission collectors of a denom (the issuer, or its split collectors) were credited
+//exactly the sum of the rounded per-sender commission shares
+pub fn verify_commission_credit(detailed: &TxResult, denom: &str) -> Result<(), TxError> {
+    let owed = detailed
+        .fee_shares
+        .iter()
+        .filter(|share| share.denom == denom)
+        .map(|share| share.commission)
+        .sum::<i128>();
+    let credited = detailed
+        .tagged_changes
+        .iter()
+        .filter(|tagged| {
+            tagged.denom == denom
+                && tagged.amount > 0
+                && tagged.category == ChangeCategory::Commission
+        })
+        .map(|tagged| tagged.amount)
+        .sum::<i128>();
+
+    if credited != owed {
+        return Err(TxError::CommissionMismatch {
+            denom: denom.to_string(),
+            owed,
+            credited,
+        });
+    }
+    Ok(())
+}
+
+//The account with the largest total debit (amount + burn + commission) on a denom, and that debit.
+//Credits the account receives in the same tx don't offset it. Ties go to the first address in
+//lexicographic order; a denom nobody sends gives `TxError::NoInputs`.
+pub fn max_account_debit(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    denom: &str,
+) -> Result<(String, i128), TxError> {
+    let result = calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)?;
+    let mut debits: BTreeMap<&str, i128> = BTreeMap::new();
+    for tagged in result.tagged_changes.iter() {
+        if tagged.denom == denom && tagged.amount < 0 {
+            *debits.entry(&tagged.address).or_insert(0) -= tagged.amount;
+        }
+    }
+
+    debits
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, debit)| *debit)
+        .map(|(address, debit)| (address.to_string(), debit))
+        .ok_or(TxError::NoInputs)
+}
+
+//Per (sender, denom), the fees charged (burn + commission) as a fraction of the amount sent. Fee-free
+//sends give 0, zero amounts are skipped. A rejected tx charges nothing, so it gives an empty map.
+pub fn fee_incidence(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> HashMap<(String, String), f64> {
+    let result =
+        match calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx) {
+            Ok(result) => result,
+            Err(_) => return HashMap::new(),
+        };
+    let mut sent: HashMap<(String, String), i128> = HashMap::new();
+    for input in result.multi_send_tx.inputs.iter() {
+        for coin in input.coins.iter() {
+            *sent
+                .entry((input.address.clone(), coin.denom.clone()))
+                .or_insert(0) += coin.amount;
+        }
+    }
+    let mut fees: HashMap<(String, String), i128> = HashMap::new();
+    for share in result.fee_shares.iter() {
+        *fees
+            .entry((share.address.clone(), share.denom.clone()))
+            .or_insert(0) += share.burn + share.commission;
+    }
+
+    sent.into_iter()
+        .filter(|(_, amount)| *amount != 0)
+        .map(|(key, amount)| {
+            let fee = fees.get(&key).copied().unwrap_or(0);
+            (key, fee as f64 / amount as f64)
+        })
+        .collect()
+}
+
+//floor(a * b / c) for non-negative a, b and positive c, exact even when a * b overflows an i128
+fn mul_div_floor(a: i128, b: i128, c: i128) -> Result<i128, TxError> {
+    if let Some(product) = a.checked_mul(b) {
+        return Ok(product / c);
+    }
+    let (a, b, c) = (a as u128, b as u128, c as u128);
+    //256-bit product as (high, low) halves, built from 64-bit limbs
+    let mask = u64::MAX as u128;
+    let (a_high, a_low, b_high, b_low) = (a >> 64, a & mask, b >> 64, b & mask);
+    let low_low = a_low * b_low;
+    let middle = a_high * b_low + (low_low >> 64);
+    let middle = (middle >> 64, a_low * b_high + (middle & mask));
+    let high = a_high * b_high + middle.0 + (middle.1 >> 64);
+    let low = (middle.1 << 64) | (low_low & mask);
+    //Long division a bit at a time, the remainder stays below c < 2^127 so it can't overflow
+    let (mut quotient, mut remainder) = (0_u128, 0_u128);
+    for bit in (0..256).rev() {
+        let next = match bit >= 128 {
+            true => (high >> (bit - 128)) & 1,
+            false => (low >> bit) & 1,
+        };
+        remainder = (remainder << 1) | next;
+        quotient = quotient.checked_mul(2).ok_or(TxError::Overflow)?;
+        if remainder >= c {
+            remainder -= c;
+            quotient |= 1;
+        }
+    }
+    i128::try_from(quotient).map_err(|_| TxError::Overflow)
+}
+
+//Balance changes of the tx had the address's inputs failed. The outputs of each denom it sent shrink
+//proportionally to keep the tx balanced (leftover units from flooring go to the earliest outputs),
+//so the non-issuer sums change and the burn & commission are redistributed over the other senders.
+pub fn without_sender(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    mut multi_send_tx: MultiSend,
+    address: &str,
+) -> Result<Vec<Balance>, TxError> {
+    multi_send_tx.validate_multi_send_tx()?;
+    //Per denom balancing with no negatives keeps what's removed within the outputs it shrinks
+    multi_send_tx.validate_structure()?;
+    let mut removed: HashMap<String, i128> = HashMap::new();
+    for input in multi_send_tx.inputs.iter() {
+        if input.address == address {
+            for coin in input.coins.iter() {
+                accumulate(removed.entry(coin.denom.clone()).or_insert(0), coin.amount)?;
+            }
+        }
+    }
+    multi_send_tx
+        .inputs
+        .retain(|input| input.address != address);
+
+    for (denom, removed) in removed {
+        let mut coins = multi_send_tx
+            .outputs
+            .iter_mut()
+            .flat_map(|output| output.coins.iter_mut())
+            .filter(|coin| coin.denom == denom)
+            .collect::<Vec<&mut Coin>>();
+        let total = coins
+            .iter()
+            .try_fold(0_i128, |acc, coin| acc.checked_add(coin.amount))
+            .ok_or(TxError::Overflow)?;
+        if total == 0 {
+            continue;
+        }
+        let kept = total.checked_sub(removed).ok_or(TxError::Overflow)?;
+        let scaled = coins
+            .iter()
+            .map(|coin| mul_div_floor(coin.amount, kept, total))
+            .collect::<Result<Vec<i128>, TxError>>()?;
+        //Each scaled amount is at most its coin's, so their sum stays within total
+        let mut leftover = kept - scaled.iter().sum::<i128>();
+        for (coin, scaled) in coins.iter_mut().zip(scaled) {
+            let bump = (leftover > 0 && scaled < coin.amount) as i128;
+            leftover -= bump;
+            coin.amount = scaled + bump;
+        }
+    }
+
+    Ok(
+        calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)?
+            .balance_changes,
+    )
+}
+
+//Denom -> net change of the issuer's balance in each denom it issues, i.e. the commission it earned
+//and anything it received, less its own sends. A rejected tx changes nothing, so it gives an empty map.
+pub fn issuer_net_change(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+    issuer: &str,
+) -> HashMap<String, i128> {
+    let result =
+        match calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx) {
+            Ok(result) => result,
+            Err(_) => return HashMap::new(),
+        };
+    let issued = result
+        .definitions
+        .iter()
+        .filter(|definition| definition.is_issuer(issuer))
+        .map(|definition| definition.denom.as_str())
+        .collect::<HashSet<&str>>();
+
+    result
+        .balance_changes
+        .iter()
+        .filter(|balance| balance.address == issuer)
+        .flat_map(|balance| balance.coins.iter())
+        .filter(|coin| issued.contains(coin.denom.as_str()))
+        .map(|coin| (coin.denom.clone(), coin.amount))
+        .collect()
+}
+
+//The detailed result as debit/credit pairs, ordered by denom. Senders' transfers are paired with the
+//recipients' credits, their burns with `BURN_ACCOUNT` and their commissions with the collectors. Each
+//account's changes are netted per kind first, so an issuer paying commission to itself gets no entry.
+//Whatever doesn't pair by kind (e.g. the fees withheld from gross mode credits) is paired in order.
+//Every entry moves its amount out of one account and into another, so the debits and credits of a
+//denom always balance.
+pub fn to_ledger_entries(detailed: &TxResult) -> Vec<LedgerEntry> {
+    let denoms = detailed
+        .tagged_changes
+        .iter()
+        .map(|tagged| tagged.denom.as_str())
+        .collect::<BTreeSet<&str>>();
+    let mut entries = vec![];
+    for denom in denoms {
+        //(kind, account, net change), in the order the accounts were first changed
+        let mut nets: Vec<(ChangeCategory, &str, i128)> = vec![];
+        for tagged in detailed
+            .tagged_changes
+            .iter()
+            .filter(|tagged| tagged.denom == denom)
+        {
+            let category = match tagged.category {
+                ChangeCategory::Combined => ChangeCategory::Transfer,
+                category => category,
+            };
+            match nets
+                .iter_mut()
+                .find(|(kind, account, _)| *kind == category && *account == tagged.address)
+            {
+                Some(net) => net.2 += tagged.amount,
+                None => nets.push((category, &tagged.address, tagged.amount)),
+            }
+        }
+
+        let (mut debits, mut credits): (Vec<_>, Vec<_>) = nets
+            .into_iter()
+            .filter(|(_, _, amount)| *amount != 0)
+            .map(|(kind, account, amount)| (kind, account, amount.abs(), amount < 0))
+            .partition(|(_, _, _, is_debit)| *is_debit);
+        let debited = debits.iter().map(|debit| debit.2).sum::<i128>();
+        let credited = credits.iter().map(|credit| credit.2).sum::<i128>();
+        //The burnt coins are whatever left the senders without reaching an account
+        if debited > credited {
+            credits.push((
+                ChangeCategory::Burn,
+                BURN_ACCOUNT,
+                debited - credited,
+                false,
+            ));
+        }
+
+        let kinds = [
+            Some(ChangeCategory::Transfer),
+            Some(ChangeCategory::Burn),
+            Some(ChangeCategory::Commission),
+            None,
+        ];
+        for kind in kinds {
+            let matches = |category: ChangeCategory| kind.is_none_or(|kind| kind == category);
+            for debit in debits.iter_mut().filter(|debit| matches(debit.0)) {
+                while debit.2 > 0 {
+                    let Some(credit) = credits
+                        .iter_mut()
+                        .find(|credit| matches(credit.0) && credit.2 > 0)
+                    else {
+                        break;
+                    };
+                    let amount = min(debit.2, credit.2);
+                    debit.2 -= amount;
+                    credit.2 -= amount;
+                    if debit.1 != credit.1 {
+                        entries.push(LedgerEntry {
+                            debit_account: debit.1.to_string(),
+                            credit_account: credit.1.to_string(),
+                            denom: denom.to_string(),
+                            amount,
+                        });
                     }
                 }
             }
         }
     }
+    entries
+}
+
+//Per (sender, denom), the balance the sender needs for the tx to succeed: everything it is debited
+//(amount + burn share + commission share), not offset by what it receives in the same tx. An invalid
+//tx can't be funded, so it gives an empty map.
+pub fn required_balances(
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> HashMap<(String, String), i128> {
+    //Run against no balances, letting every sender go negative so the debits can be collected
+    let options = TxOptions {
+        audit: true,
+        allow_negative: multi_send_tx
+            .inputs
+            .iter()
+            .map(|input| input.address.clone())
+            .collect(),
+        ..TxOptions::default()
+    };
+    let result = match calculate_balance_changes_with_options(
+        vec![],
+        definitions,
+        multi_send_tx,
+        &options,
+    ) {
+        Ok(result) => result,
+        Err(_) => return HashMap::new(),
+    };
+    let mut required: HashMap<(String, String), i128> = HashMap::new();
+    for mutation in result.audit.iter().filter(|mutation| mutation.delta < 0) {
+        *required
+            .entry((mutation.address.clone(), mutation.denom.clone()))
+            .or_insert(0) -= mutation.delta;
+    }
+    required
+}
+
+//Denom -> change in circulating supply, i.e. minus the total burnt (fees and outputs to the burn
+//addresses). Commission only moves coins to the
+//collectors, so it doesn't change the supply. A rejected tx burns nothing, so it gives an empty map.
+pub fn supply_delta(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> HashMap<String, i128> {
+    let result =
+        match calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx) {
+            Ok(result) => result,
+            Err(_) => return HashMap::new(),
+        };
+    let mut deltas: HashMap<String, i128> = HashMap::new();
+    for share in result.fee_shares.iter() {
+        *deltas.entry(share.denom.clone()).or_insert(0) -= share.burn;
+    }
+    for (denom, burnt) in result.burnt_outputs {
+        *deltas.entry(denom).or_insert(0) -= burnt;
+    }
+    deltas
+}
+
+//Balance changes of a tx against balances given as flat (address, denom, amount) rows. Rows repeating
+//an address and denom are summed.
+pub fn calculate_from_rows(
+    rows: Vec<(String, String, i128)>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<Vec<Balance>, TxError> {
+    let mut balances = Balances::default();
+    for (address, denom, amount) in rows.iter() {
+        balances.credit(address, denom, *amount)?;
+    }
+    Ok(
+        calculate_balance_changes_detailed(balances.into_vec(), definitions, multi_send_tx)?
+            .balance_changes,
+    )
+}
+
+//Parses newline-delimited JSON holding one MultiSend per line, e.g. a tx log to replay with
+//`apply_batch`. Blank lines are skipped; a line that doesn't parse is reported by its (1-based) number.
+pub fn from_ndjson(input: &str) -> Result<Vec<MultiSend>, TxError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            serde_json::from_str::<MultiSend>(line).map_err(|err| TxError::InvalidNdjson {
+                line: idx + 1,
+                reason: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+//Applies the txs in order, each against the balances left by the previous ones, and returns the
+//final balances sorted by address then denom. The first rejected tx aborts the whole batch.
+pub fn apply_batch(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    txs: Vec<MultiSend>,
+) -> Result<Vec<Balance>, TxError> {
+    apply_batch_with_reject_hook(original_balances, definitions, txs, |_, _| {})
+}
+
+//`apply_batch`, calling `on_reject` with the index and error of the rejected tx before aborting
+pub fn apply_batch_with_reject_hook(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    txs: Vec<MultiSend>,
+    mut on_reject: impl FnMut(usize, &TxError),
+) -> Result<Vec<Balance>, TxError> {
+    let mut balances = Balances::try_from(original_balances)?;
+    for (index, tx) in txs.into_iter().enumerate() {
+        let changes = match calculate_balance_changes_detailed(
+            balances.clone().into_vec(),
+            definitions.clone(),
+            tx,
+        ) {
+            Ok(result) => result.balance_changes,
+            Err(err) => {
+                on_reject(index, &err);
+                return Err(err);
+            }
+        };
+        for change in changes.iter() {
+            for coin in change.coins.iter() {
+                balances.credit(&change.address, &coin.denom, coin.amount)?;
+            }
+        }
+    }
+    Ok(balances.into_vec())
+}
+
+//Applies the txs in order like `apply_batch`, but a rejected tx is skipped instead of aborting the
+//batch. Returns the final balances with stats over the whole batch: the distinct denoms and accounts
+//of the accepted txs, their burn and commission totals, and the number of txs rejected.
+pub fn apply_batch_skipping_rejected(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    txs: Vec<MultiSend>,
+) -> Result<(Vec<Balance>, ProcessingStats), TxError> {
+    let mut balances = Balances::try_from(original_balances)?;
+    let mut stats = ProcessingStats::default();
+    let (mut denoms, mut accounts) = (BTreeSet::new(), BTreeSet::new());
+    for tx in txs {
+        let result = match calculate_balance_changes_detailed(
+            balances.clone().into_vec(),
+            definitions.clone(),
+            tx,
+        ) {
+            Ok(result) => result,
+            Err(_) => {
+                stats.rejected += 1;
+                continue;
+            }
+        };
+        for balance in result
+            .multi_send_tx
+            .inputs
+            .iter()
+            .chain(result.multi_send_tx.outputs.iter())
+        {
+            denoms.extend(balance.coins.iter().map(|coin| coin.denom.clone()));
+        }
+        accumulate(&mut stats.total_burnt, result.stats.total_burnt)?;
+        accumulate(&mut stats.total_commission, result.stats.total_commission)?;
+        for change in result.balance_changes.iter() {
+            accounts.insert(change.address.clone());
+            for coin in change.coins.iter() {
+                balances.credit(&change.address, &coin.denom, coin.amount)?;
+            }
+        }
+    }
+    stats.denoms_processed = denoms.len();
+    stats.accounts_touched = accounts.len();
+    Ok((balances.into_vec(), stats))
+}
+
+//Fees a tx charged, per denom: the burn removed from the supply and the commission paid to the
+//collectors. Outputs to `TxOptions::burn_addresses` aren't fees, so they aren't included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProtocolRevenue {
+    pub burnt: HashMap<String, i128>,
+    pub commission: HashMap<String, i128>,
+}
+
+impl ProtocolRevenue {
+    //Denom -> burn + commission
+    pub fn total_fees_per_denom(&self) -> HashMap<String, i128> {
+        let mut totals = self.burnt.clone();
+        for (denom, commission) in self.commission.iter() {
+            *totals.entry(denom.clone()).or_insert(0) += commission;
+        }
+        totals
+    }
+}
+
+pub fn protocol_revenue(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<ProtocolRevenue, TxError> {
+    let result = calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)?;
+    let mut revenue = ProtocolRevenue::default();
+    for share in result.fee_shares.iter() {
+        *revenue.burnt.entry(share.denom.clone()).or_insert(0) += share.burn;
+        *revenue.commission.entry(share.denom.clone()).or_insert(0) += share.commission;
+    }
+    Ok(revenue)
+}
+
+//Every problem found in a tx, by kind, instead of only the first one. Each list is sorted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationReport {
+    //Inputs and outputs add up to the same amount
+    pub sum_ok: bool,
+    //Denoms in the tx without a definition
+    pub unknown_denoms: Vec<String>,
+    //Defined denoms with a negative or non-finite rate, or fees above 100%
+    pub invalid_rates: Vec<String>,
+    //(sender, denom) pairs whose balance can't cover what they are debited
+    pub insufficient: Vec<(String, String)>,
+}
+
+//Collects every validation issue of the tx without failing fast. The balances are checked against
+//`required_balances` on the coins with a definition; when the fees can't be computed (e.g. the sums
+//don't match) only the amounts sent are checked.
+pub fn validation_report(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> ValidationReport {
+    let defined = definitions
+        .iter()
+        .map(|definition| definition.denom.as_str())
+        .collect::<HashSet<&str>>();
+    let unknown_denoms = multi_send_tx
+        .inputs
+        .iter()
+        .chain(multi_send_tx.outputs.iter())
+        .flat_map(|balance| balance.coins.iter())
+        .filter(|coin| !defined.contains(coin.denom.as_str()))
+        .map(|coin| coin.denom.clone())
+        .collect::<BTreeSet<String>>();
+    let invalid_rates = definitions
+        .iter()
+        .filter(|definition| {
+            let rates = [definition.burn_rate, definition.commission_rate];
+            rates.iter().any(|rate| !rate.is_finite() || *rate < 0_f64)
+                || rates.iter().sum::<f64>() > 1_f64
+        })
+        .map(|definition| definition.denom.clone())
+        .collect::<BTreeSet<String>>();
+
+    //The tx without its undefined coins, so those don't also block the fee computation
+    let known = |balances: &[Balance]| {
+        balances
+            .iter()
+            .map(|balance| Balance {
+                address: balance.address.clone(),
+                coins: balance
+                    .coins
+                    .iter()
+                    .filter(|coin| defined.contains(coin.denom.as_str()))
+                    .cloned()
+                    .collect::<Vec<Coin>>(),
+            })
+            .filter(|balance| !balance.coins.is_empty())
+            .collect::<Vec<Balance>>()
+    };
+    let known_tx = MultiSend {
+        inputs: known(&multi_send_tx.inputs),
+        outputs: known(&multi_send_tx.outputs),
+        memo: None,
+    };
+    let mut required = required_balances(definitions.clone(), known_tx.clone());
+    if required.is_empty() {
+        for input in known_tx.inputs.iter() {
+            for coin in input.coins.iter() {
+                *required
+                    .entry((input.address.clone(), coin.denom.clone()))
+                    .or_insert(0) += coin.amount;
+            }
+        }
+    }
+    //Balances whose repeated entries overflow can't be read, so they cover nothing
+    let balances = Balances::try_from(original_balances).unwrap_or_default();
+    let insufficient = required
+        .into_iter()
+        .filter(|((address, denom), needed)| balances.get(address, denom) < *needed)
+        .map(|(key, _)| key)
+        .collect::<BTreeSet<(String, String)>>();
+
+    ValidationReport {
+        sum_ok: multi_send_tx.validate_multi_send_tx().is_ok(),
+        unknown_denoms: unknown_denoms.into_iter().collect(),
+        invalid_rates: invalid_rates.into_iter().collect(),
+        insufficient: insufficient.into_iter().collect(),
+    }
+}
+
+//Balance changes of a tx together with the balances they result in
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub deltas: Vec<Balance>,
+    //Every original balance with the deltas applied, plus the accounts the tx creates, sorted by
+    //address then denom
+    pub final_balances: Vec<Balance>,
+}
+
+pub fn preview(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Result<Preview, TxError> {
+    let mut final_balances = Balances::try_from(original_balances.clone())?;
+    let deltas = calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx)?
+        .balance_changes;
+    for delta in deltas.iter() {
+        for coin in delta.coins.iter() {
+            final_balances.credit(&delta.address, &coin.denom, coin.amount)?;
+        }
+    }
+    Ok(Preview {
+        deltas,
+        final_balances: final_balances.into_vec(),
+    })
+}
+
+//(address, denom) pairs whose balance the tx brings to exactly zero, sorted. A rejected tx empties
+//nothing, so it gives an empty list.
+pub fn empties_account(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Vec<(String, String)> {
+    let changes = match calculate_balance_changes_map(
+        original_balances.clone(),
+        definitions,
+        multi_send_tx,
+    ) {
+        Ok(changes) => changes,
+        Err(_) => return vec![],
+    };
+
+    let mut emptied = changes
+        .into_iter()
+        .flat_map(|(address, coins)| {
+            coins
+                .into_iter()
+                .map(move |(denom, change)| (address.clone(), denom, change))
+        })
+        .filter(|(address, denom, change)| {
+            *change != 0
+                && original_balances
+                    .get(address, denom)
+                    .is_ok_and(|balance| balance.checked_add(*change) == Some(0))
+        })
+        .map(|(address, denom, _)| (address, denom))
+        .collect::<Vec<(String, String)>>();
+    emptied.sort();
+    emptied
+}
+
+//Denoms charged some burn in the tx with their total burnt, largest first (ties by denom). A rejected
+//tx burns nothing, so it gives an empty list.
+pub fn denoms_by_burn(
+    original_balances: Vec<Balance>,
+    definitions: Vec<DenomDefinition>,
+    multi_send_tx: MultiSend,
+) -> Vec<(String, i128)> {
+    let result =
+        match calculate_balance_changes_detailed(original_balances, definitions, multi_send_tx) {
+            Ok(result) => result,
+            Err(_) => return vec![],
+        };
+
+    let mut burns: BTreeMap<String, i128> = BTreeMap::new();
+    for share in result.fee_shares.iter().filter(|share| share.burn != 0) {
+        *burns.entry(share.denom.clone()).or_insert(0) += share.burn;
+    }
+    let mut burns = burns.into_iter().collect::<Vec<(String, i128)>>();
+    //Stable, so equal burns stay in denom order
+    burns.sort_by_key(|(_, burnt)| std::cmp::Reverse(*burnt));
+    burns
+}
+
+//Whether the tx charges no burn or commission at all: every coin sent either has zero rates or is
+//sent by its issuer while exempt from the nonzero fees. Such a tx is a plain transfer.
+pub fn is_fee_free(definitions: &[DenomDefinition], multi_send_tx: &MultiSend) -> bool {
+    multi_send_tx.inputs.iter().all(|input| {
+        input.coins.iter().all(|coin| {
+            match definitions
+                .iter()
+                .find(|definition| definition.denom == coin.denom)
+            {
+                Some(definition) => send_is_fee_free(definition, &input.address),
+                //Unknown denoms are rejected before any fee is charged
+                None => true,
+            }
+        })
+    })
+}
+
+//Whether a send of the denom by the address is charged neither burn nor commission
+fn send_is_fee_free(definition: &DenomDefinition, address: &str) -> bool {
+    send_is_fee_free_with(
+        definition,
+        address,
+        definition.burn_rate == 0_f64,
+        definition.commission_rate == 0_f64,
+    )
+}
+
+//`send_is_fee_free` given whether the burn and commission rates are zero, e.g. for exact rates
+fn send_is_fee_free_with(
+    definition: &DenomDefinition,
+    address: &str,
+    zero_burn: bool,
+    zero_commission: bool,
+) -> bool {
+    match definition.is_issuer(address) {
+        true => {
+            (zero_burn || definition.issuer_exempt_burn)
+                && (zero_commission || definition.issuer_exempt_commission)
+        }
+        false => {
+            zero_burn
+                && zero_commission
+                && definition.flat_burn_fee == 0
+                && definition.flat_commission_fee == 0
+        }
+    }
+}
+
+//Unrounded (burn, commission) on a denom, i.e. total_bc * burn_rate and total_bc * commission_rate,
+//to compare against the rounded-up amounts actually charged. Returns (0, 0) for an unknown denom.
+pub fn theoretical_fees(
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    denom: &str,
+) -> Result<(f64, f64), TxError> {
+    let tx_data = TxData::unvalidated(multi_send_tx, definitions)?;
+    Ok(match tx_data.denom_definitions_map.get(denom) {
+        Some(definition) => {
+            let (total_bc, _) = tx_data.bc_base(denom);
+            (
+                total_bc as f64 * definition.burn_rate,
+                total_bc as f64 * definition.commission_rate,
+            )
+        }
+        None => (0_f64, 0_f64),
+    })
+}
+
+//Burn actually charged on a denom relative to the non-issuer inputs. Since the base is capped at
+//min(non_issuer_input_sum, non_issuer_output_sum), this can be lower than the nominal burn_rate.
+//Returns 0 when no non-issuer sends the denom.
+pub fn effective_burn_rate(
+    definitions: &[DenomDefinition],
+    multi_send_tx: &MultiSend,
+    denom: &str,
+) -> Result<f64, TxError> {
+    let tx_data = TxData::unvalidated(multi_send_tx, definitions)?;
+    let definition = match tx_data.denom_definitions_map.get(denom) {
+        Some(definition) => definition,
+        None => return Ok(0_f64),
+    };
+    let (total_bc, non_issuer_input_sum) = tx_data.bc_base(denom);
+    if non_issuer_input_sum == 0 {
+        return Ok(0_f64);
+    }
+
+    let total_burn: i128 = tx_data
+        .multi_send_tx
+        .inputs
+        .iter()
+        .filter(|input| !definition.is_issuer(&input.address))
+        .flat_map(|input| input.coins.iter())
+        .filter(|coin| coin.denom == denom)
+        .map(|coin| {
+            evaluate_rate(
+                coin.amount,
+                definition.burn_rate,
+                total_bc,
+                non_issuer_input_sum,
+            )
+        })
+        .sum();
+    Ok(total_burn as f64 / non_issuer_input_sum as f64)
+}
+
+//Inverse of `calculate_balance_changes_with_options`: builds the tx that, processed with `options`,
+//credits each recipient exactly its desired amount. Under `AmountSemantics::Net` those are the outputs
+//themselves, the senders paying the fees on top; under `AmountSemantics::Gross` the outputs are grossed
+//up so the withheld fees leave the desired amounts. The total of every denom is split evenly between
+//`senders` (leftover units go to the first ones). Balances are not checked.
+pub fn inputs_for_outputs(
+    definitions: &[DenomDefinition],
+    desired_outputs: Vec<Balance>,
+    senders: Vec<String>,
+    options: &TxOptions,
+) -> Result<MultiSend, TxError> {
+    //Any balance is sufficient, we only care about the fees
+    struct Unlimited;
+    impl BalanceStore for Unlimited {
+        fn get(&self, _address: &str, _denom: &str) -> Result<i128, TxError> {
+            Ok(i128::MAX)
+        }
+    }
+
+    //A combined rate of 100% or more eats the whole gross amount, so no amount can be delivered
+    for coin in desired_outputs
+        .iter()
+        .flat_map(|output| output.coins.iter())
+    {
+        if let Some(definition) = definitions.iter().find(|d| d.denom == coin.denom) {
+            if options.amount_semantics == AmountSemantics::Gross
+                && definition.burn_rate + definition.commission_rate >= 1_f64
+            {
+                return Err(TxError::NoSolution {
+                    denom: coin.denom.clone(),
+                });
+            }
+        }
+    }
+
+    let desired = normalize_balances(&desired_outputs);
+    let mut multi_send_tx = MultiSend {
+        inputs: split_between_senders(&desired, &senders)?,
+        outputs: desired.clone(),
+        memo: None,
+    };
+
+    //Each round tops the outputs up by what their recipients are still short of. The fees grow by
+    //less than the top-up, so the shortfall shrinks until every recipient gets exactly its amount.
+    for _ in 0..100 {
+        let result = calculate_balance_changes_with_store(
+            &Unlimited,
+            definitions.to_vec(),
+            multi_send_tx.clone(),
+            options,
+        )?;
+        let credited = normalize_balances(&result.balance_changes);
+
+        let mut settled = true;
+        for (output, wanted) in multi_send_tx.outputs.iter_mut().zip(desired.iter()) {
+            for (coin, wanted_coin) in output.coins.iter_mut().zip(wanted.coins.iter()) {
+                let got = credited
+                    .iter()
+                    .find(|balance| balance.address == output.address)
+                    .and_then(|balance| balance.coins.iter().find(|c| c.denom == coin.denom))
+                    .map(|c| c.amount)
+                    .unwrap_or(0);
+                if got != wanted_coin.amount {
+                    let shortfall = wanted_coin
+                        .amount
+                        .checked_sub(got)
+                        .ok_or(TxError::Overflow)?;
+                    coin.amount = coin
+                        .amount
+                        .checked_add(shortfall)
+                        .ok_or(TxError::Overflow)?;
+                    settled = false;
+                }
+            }
+        }
+        if settled {
+            return Ok(multi_send_tx);
+        }
+        multi_send_tx.inputs = split_between_senders(&multi_send_tx.outputs, &senders)?;
+    }
+
+    Err(TxError::NoSolution {
+        denom: desired
+            .iter()
+            .flat_map(|output| output.coins.iter())
+            .map(|coin| coin.denom.clone())
+            .next()
+            .unwrap_or_default(),
+    })
+}
+
+//Inputs funding `outputs`, with each denom's total split evenly between the senders
+fn split_between_senders(outputs: &[Balance], senders: &[String]) -> Result<Vec<Balance>, TxError> {
+    if senders.is_empty() {
+        return Err(TxError::NoInputs);
+    }
+
+    let mut totals: BTreeMap<String, i128> = BTreeMap::new();
+    for coin in outputs.iter().flat_map(|output| output.coins.iter()) {
+        *totals.entry(coin.denom.clone()).or_insert(0) += coin.amount;
+    }
+
+    let count = senders.len() as i128;
+    Ok(senders
+        .iter()
+        .enumerate()
+        .map(|(idx, sender)| Balance {
+            address: sender.clone(),
+            coins: totals
+                .iter()
+                .map(|(denom, total)| Coin {
+                    denom: denom.clone(),
+                    amount: total / count + i128::from((idx as i128) < total % count),
+                    meta: None,
+                })
+                .filter(|coin| coin.amount != 0)
+                .collect::<Vec<Coin>>(),
+        })
+        .collect::<Vec<Balance>>())
+}
+
+//Source of the original balances used for the sufficiency checks. Missing entries read as 0, and a
+//failed lookup rejects the tx.
+pub trait BalanceStore {
+    fn get(&self, address: &str, denom: &str) -> Result<i128, TxError>;
+}
+
+//In-memory store over a plain list of balances, summing repeated entries
+impl BalanceStore for Vec<Balance> {
+    fn get(&self, address: &str, denom: &str) -> Result<i128, TxError> {
+        self.iter()
+            .filter(|balance| balance.address == address)
+            .flat_map(|balance| balance.coins.iter())
+            .filter(|coin| coin.denom == denom)
+            .try_fold(0_i128, |acc, coin| acc.checked_add(coin.amount))
+            .ok_or(TxError::Overflow)
+    }
+}
+
+//Store over TxData's address -> denom -> amount map
+impl BalanceStore for Balances {
+    fn get(&self, address: &str, denom: &str) -> Result<i128, TxError> {
+        Ok(Balances::get(self, address, denom))
+    }
+}
+
+//Accumulates a delta onto an address's change for a denom, creating the entries as needed
+//and recording it in the audit log if one is kept
+fn add_balance_change(
+    coin_balance_changes_map: &mut HashMap<String, HashMap<String, i128>>,
+    audit: &mut Option<Vec<Mutation>>,
+    address: &str,
+    denom: &str,
+    delta: i128,
+    reason: ChangeCategory,
+) -> Result<(), TxError> {
+    accumulate(
+        coin_balance_changes_map
+            .entry(address.to_string())
+            .or_default()
+            .entry(denom.to_string())
+            .or_insert(0),
+        delta,
+    )?;
+    if let Some(audit) = audit {
+        if delta != 0 {
+            audit.push(Mutation {
+                address: address.to_string(),
+                denom: denom.to_string(),
+                delta,
+                reason,
+            });
+        }
+    }
+    Ok(())
+}
+
+//Adds to a running amount, failing with `TxError::Overflow` instead of wrapping or panicking
+fn accumulate(total: &mut i128, amount: i128) -> Result<(), TxError> {
+    *total = total.checked_add(amount).ok_or(TxError::Overflow)?;
+    Ok(())
+}
+
+//(sender, denom) -> (amount, fees) debited so far
+type SenderDebits = HashMap<(String, String), (i128, i128)>;
+
+//Adds a send and its fees to the sender's debits of the denom, and rejects the tx if the balance can't
+//cover all of them together. The error reports the totals.
+fn check_sufficient_balance(
+    debited: &mut SenderDebits,
+    store: &dyn BalanceStore,
+    options: &TxOptions,
+    address: &str,
+    coin: &Coin,
+    coin_fees: i128,
+) -> Result<(), TxError> {
+    let (amount, fees) = debited
+        .entry((address.to_string(), coin.denom.clone()))
+        .or_insert((0, 0));
+    *amount = amount.checked_add(coin.amount).ok_or(TxError::Overflow)?;
+    *fees = fees.checked_add(coin_fees).ok_or(TxError::Overflow)?;
+    let debit = amount.checked_add(*fees).ok_or(TxError::Overflow)?;
+    let available = store.get(address, &coin.denom)?;
+    if available < debit && !options.allow_negative.contains(address) {
+        return Err(TxError::InsufficientBalance {
+            address: address.to_string(),
+            denom: coin.denom.clone(),
+            amount: *amount,
+            fees: *fees,
+            available,
+        });
+    }
+    Ok(())
+}
+
+//A negative fee (e.g. from a negative rate that skipped validation) would credit the sender and
+//debit the issuer, so it is treated as a bug rather than applied
+fn check_fees_nonnegative(
+    burn_amount: i128,
+    commission_amount: i128,
+    address: &str,
+    coin: &Coin,
+) -> Result<(), TxError> {
+    if burn_amount < 0 || commission_amount < 0 {
+        return Err(TxError::InternalInvariant {
+            reason: format!(
+                "negative fee (burn {}, commission {}) on {} for coin {}",
+                burn_amount, commission_amount, address, coin.denom
+            ),
+        });
+    }
+    Ok(())
+}
+
+//Splits `total` by the weights with the largest remainder method, so the parts add up exactly to
+//`total`. Leftover units go to the largest fractional parts, ties broken by address order.
+fn split_by_weights(total: i128, weights: &[(String, f64)]) -> Vec<(String, i128)> {
+    let mut parts = weights
+        .iter()
+        .map(|(address, weight)| {
+            let raw = total as f64 * weight;
+            (address.clone(), raw.floor() as i128, raw - raw.floor())
+        })
+        .collect::<Vec<(String, i128, f64)>>();
+    let leftover = total - parts.iter().map(|(_, floor, _)| floor).sum::<i128>();
+
+    let mut order = (0..parts.len()).collect::<Vec<usize>>();
+    order.sort_by(|a, b| {
+        parts[*b]
+            .2
+            .total_cmp(&parts[*a].2)
+            .then_with(|| parts[*a].0.cmp(&parts[*b].0))
+    });
+    for idx in order.into_iter().take(leftover.max(0) as usize) {
+        parts[idx].1 += 1;
+    }
+
+    parts
+        .into_iter()
+        .map(|(address, amount, _)| (address, amount))
+        .collect()
+}
+
+//Records a nonzero balance change along with what caused it
+fn tag_change(
+    tagged_changes: &mut Vec<TaggedChange>,
+    address: &str,
+    denom: &str,
+    amount: i128,
+    category: ChangeCategory,
+) {
+    if amount != 0 {
+        tagged_changes.push(TaggedChange {
+            address: address.to_string(),
+            denom: denom.to_string(),
+            amount,
+            category,
+        });
+    }
+}
+
+//Denom key used by `TxOptions::case_insensitive_denoms`
+fn normalize_denom(denom: &str) -> String {
+    denom.trim().to_lowercase()
+}
+
+//Rejects txs with more input/output entries than the options allow
+fn validate_entry_limits(multi_send_tx: &MultiSend, options: &TxOptions) -> Result<(), TxError> {
+    let limits = [
+        ("inputs", multi_send_tx.inputs.len(), options.max_inputs),
+        ("outputs", multi_send_tx.outputs.len(), options.max_outputs),
+    ];
+    for (kind, count, limit) in limits {
+        if let Some(limit) = limit {
+            if count > limit {
+                return Err(TxError::TooManyEntries { kind, count, limit });
+            }
+        }
+    }
+    Ok(())
+}
+
+//Rejects txs with an output coin above its denom's `TxOptions::max_transfer` cap
+fn validate_transfer_caps(multi_send_tx: &MultiSend, options: &TxOptions) -> Result<(), TxError> {
+    for coin in multi_send_tx
+        .outputs
+        .iter()
+        .flat_map(|output| output.coins.iter())
+    {
+        if let Some(cap) = options.max_transfer.get(&coin.denom) {
+            if coin.amount > *cap {
+                return Err(TxError::TransferCapExceeded {
+                    denom: coin.denom.clone(),
+                    amount: coin.amount,
+                    cap: *cap,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+//Ensures no balance holds a negative amount, which would corrupt the sufficiency checks
+pub fn validate_balances_nonnegative(balances: &[Balance]) -> Result<(), TxError> {
+    for balance in balances.iter() {
+        for coin in balance.coins.iter() {
+            if coin.amount < 0 {
+                return Err(TxError::NegativeAmount {
+                    address: balance.address.clone(),
+                    denom: coin.denom.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn min(a: i128, b: i128) -> i128 {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+//roundup(total_burn * input_from_account / non_issuer_input_sum)
+fn evaluate_rate(amount: i128, rate: f64, total_amount: i128, non_issuer_input_sum: i128) -> i128 {
+    roundup(raw_share(amount, rate, total_amount, non_issuer_input_sum))
+}
+
+//total_burn * input_from_account / non_issuer_input_sum, before rounding
+fn raw_share(amount: i128, rate: f64, total_amount: i128, non_issuer_input_sum: i128) -> f64 {
+    (total_amount as f64 * rate) * amount as f64 / non_issuer_input_sum as f64
+}
+
+//Helper function to round up an f64 to an i128.
+//This is a true ceiling: any positive sub-unit share (e.g. 0.01) is charged as a whole unit, as in README example #5.
+//Use `TxOptions::min_burn_floor` to waive tiny burn shares instead.
+fn roundup(n: f64) -> i128 {
+    n.ceil() as i128
+}
+
+//Rounds up, ignoring up to `epsilon` above an integer
+fn roundup_within(n: f64, epsilon: f64) -> i128 {
+    roundup(n - epsilon)
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "node-import")]
+    use crate::from_node_snapshot;
+    #[cfg(feature = "rational")]
+    use crate::RationalRate;
+    use crate::{
+        affected_addresses, apply_batch, apply_batch_skipping_rejected,
+        apply_batch_with_reject_hook, apply_tx, assert_burn_matches, calculate_balance_changes,
+        calculate_balance_changes_detailed, calculate_balance_changes_filtered,
+        calculate_balance_changes_map, calculate_balance_changes_with_options,
+        calculate_balance_changes_with_store, calculate_from_rows, denoms_by_burn,
+        effective_burn_rate, empties_account, fee_incidence, from_ndjson, inputs_for_outputs,
+        is_fee_free, issuer_net_change, max_account_debit, mul_div_floor, normalize_balances,
+        preview, protocol_revenue, recipient_credits, required_balances, run_tx, supply_delta,
+        sweep_rates, theoretical_fees, to_cosmos_json, to_csv, to_events, to_ledger_entries,
+        validate_balances_nonnegative, validation_report, verify_commission_credit,
+        verify_output_sum, without_sender, BalanceStore, TxData,
+    };
+    use crate::{
+        AmountSemantics, Balance, Balances, ChangeCategory, Coin, DenomDefinition, IndexedInput,
+        IndexedMultiSend, LedgerEntry, LiveTx, MultiSend, Mutation, ProcessingStats,
+        ResultDeviation, ShareRounding, TxError, TxOptions, TxResult, TxWarning, ValidatedTx,
+        ValidationReport, BURN_ACCOUNT,
+    };
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::error::Error;
+    use std::time::Duration;
+
+    #[test]
+    //NOTE: Example #4 from README
+    pub fn test_invalid_sum() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_invalid_sum_data();
+        assert_eq!(
+            calculate_balance_changes(original_balances, definitions, multi_send).err(),
+            Some("Invalid Multi Send Tx".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_no_issuer_on_sender_or_receiver() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let mut assertion_map = HashMap::new();
+        assertion_map.insert(
+            "account_recipient".to_string(),
+            vec![
+                Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                },
+                Coin {
+                    denom: "denom2".to_string(),
+                    amount: 1000,
+                    meta: None,
+                },
+            ],
+        );
+        assertion_map.insert(
+            "issuer_account_A".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 120,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account1".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -1200,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account2".to_string(),
+            vec![Coin {
+                denom: "denom2".to_string(),
+                amount: -2000,
+                meta: None,
+            }],
+        );
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert_changes_eq(&balance_changes, &assertion_map);
+        Ok(())
+    }
+    #[test]
+    ///NOTE: Example #2 from README
+    pub fn test_issuer_exists_on_sender_receiver() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let mut assertion_map = HashMap::new();
+        assertion_map.insert(
+            "account_recipient".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 500,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "issuer_account_A".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 560,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account1".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -715,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account2".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -385,
+                meta: None,
+            }],
+        );
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert_changes_eq(&balance_changes, &assertion_map);
+        Ok(())
+    }
+
+    #[test]
+    ///NOTE: Example #2 from README
+    pub fn test_demonstrate_rounding_up() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_rounding_up_data();
+        let mut assertion_map = HashMap::new();
+        assertion_map.insert(
+            "account_recipient".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 2,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "issuer_account_A".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 2,
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account1".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -3, //1 sent, 1 burnt, 1 sent to issuer as commission (1 = roundup(2 * 0.01 * 1 / 2))
+                meta: None,
+            }],
+        );
+        assertion_map.insert(
+            "account2".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -3, //1 sent, 1 burnt, 1 sent to issuer as commission (1 = roundup(2 * 0.01 * 1 / 2))
+                meta: None,
+            }],
+        );
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send).unwrap();
+        for balance_change in balance_changes.iter() {
+            assertion_map
+                .get(&balance_change.address)
+                .unwrap()
+                .iter()
+                .zip(balance_change.coins.clone())
+                .for_each(|(assertion_coin, coin)| {
+                    assert_eq!(assertion_coin.amount, coin.amount);
+                })
+        }
+        Ok(())
+    }
+    #[test]
+    ///NOTE: Example #3 from README
+    pub fn test_insufficient_balance() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_insufficient_balance_data();
+        assert_eq!(
+            calculate_balance_changes(original_balances, definitions, multi_send).err(),
+            Some(format!(
+                "Inssuficient wallet balance on {} for coin {}",
+                "account1", "denom1"
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_share_trace() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+
+        let share = result
+            .share_trace
+            .iter()
+            .find(|share| share.address == "account1" && share.denom == "denom1")
+            .unwrap();
+        assert!((share.raw - 80_f64).abs() < 1e-9);
+        assert_eq!(share.rounded, 80);
+        //denom2 is fully burnt on account2's send
+        assert!(result
+            .share_trace
+            .iter()
+            .any(|share| share.address == "account2"
+                && share.denom == "denom2"
+                && share.rounded == 1000));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_normalize_multi_send() -> Result<(), Box<dyn Error>> {
+        let messy = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![
+                        Coin {
+                            denom: "denom2".to_string(),
+                            amount: 100,
+                            meta: None,
+                        },
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 0,
+                            meta: None,
+                        },
+                    ],
+                },
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 30,
+                            meta: None,
+                        },
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 20,
+                            meta: None,
+                        },
+                    ],
+                },
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 50,
+                        meta: None,
+                    }],
+                },
+            ],
+            outputs: vec![
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![
+                        Coin {
+                            denom: "denom2".to_string(),
+                            amount: 100,
+                            meta: None,
+                        },
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 100,
+                            meta: None,
+                        },
+                    ],
+                },
+                Balance {
+                    address: "account_empty".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 0,
+                        meta: None,
+                    }],
+                },
+            ],
+            memo: None,
+        };
+        let canonical = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 50,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 50,
+                            meta: None,
+                        },
+                        Coin {
+                            denom: "denom2".to_string(),
+                            amount: 100,
+                            meta: None,
+                        },
+                    ],
+                },
+            ],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![
+                    Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                    Coin {
+                        denom: "denom2".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                ],
+            }],
+            memo: None,
+        };
+
+        assert_eq!(messy.normalize(), canonical);
+        assert_eq!(canonical.normalize(), canonical);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_reject_fee_over_100_percent() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_excessive_fees_data();
+        let options = TxOptions {
+            reject_fee_over_100_percent: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )
+            .err(),
+            Some(TxError::ExcessiveFees {
+                denom: "denom1".to_string()
+            })
+        );
+
+        //Accepted by default, the sender pays 100 + 70 burnt + 50 commission
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions::default(),
+        )?;
+        let account1 = result
+            .balance_changes
+            .iter()
+            .find(|balance| balance.address == "account1")
+            .unwrap();
+        assert_eq!(account1.coins[0].amount, -220);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_net_and_gross_amount_semantics() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+
+        let net = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &TxOptions::default(),
+        )?;
+        let gross = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions {
+                amount_semantics: AmountSemantics::Gross,
+                ..Default::default()
+            },
+        )?;
+
+        let amount_of = |changes: &[Balance], address: &str| {
+            changes
+                .iter()
+                .find(|balance| balance.address == address)
+                .map(|balance| balance.coins[0].amount)
+        };
+        //Net: the sender pays the 10 burnt on top of the 100 sent
+        assert_eq!(amount_of(&net.balance_changes, "account1"), Some(-110));
+        assert_eq!(
+            amount_of(&net.balance_changes, "account_recipient"),
+            Some(100)
+        );
+        //Gross: the 10 burnt comes out of the 100 sent
+        assert_eq!(amount_of(&gross.balance_changes, "account1"), Some(-100));
+        assert_eq!(
+            amount_of(&gross.balance_changes, "account_recipient"),
+            Some(90)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_unused_definition_warning() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom3")
+                .with_issuer("issuer_account_C")
+                .with_burn_rate(0_f64)
+                .with_commission_rate(0_f64),
+        );
+
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            result.warnings,
+            vec![TxWarning::UnusedDefinition {
+                denom: "denom3".to_string()
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_validate_balances_nonnegative() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, _, _) = initialize_no_issuer_on_sender_or_receiver();
+        assert_eq!(validate_balances_nonnegative(&original_balances), Ok(()));
+
+        original_balances[1].coins.push(Coin {
+            denom: "denom1".to_string(),
+            amount: -5,
+            meta: None,
+        });
+        assert_eq!(
+            validate_balances_nonnegative(&original_balances),
+            Err(TxError::NegativeAmount {
+                address: "account2".to_string(),
+                denom: "denom1".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_input_with_issuer_and_non_issuer_coins() -> Result<(), Box<dyn Error>> {
+        let original_balances = vec![Balance {
+            address: "account1".to_string(),
+            coins: vec![
+                Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                },
+                Coin {
+                    denom: "denom2".to_string(),
+                    amount: 1000,
+                    meta: None,
+                },
+            ],
+        }];
+        //account1 is the issuer of denom2 only
+        let definitions = vec![
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0.1_f64)
+                .with_commission_rate(0_f64),
+            DenomDefinition::default()
+                .with_denom("denom2")
+                .with_issuer("account1")
+                .with_burn_rate(0.1_f64)
+                .with_commission_rate(0_f64),
+        ];
+        let multi_send = MultiSend {
+            inputs: vec![Balance {
+                address: "account1".to_string(),
+                coins: vec![
+                    Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                    Coin {
+                        denom: "denom2".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                ],
+            }],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![
+                    Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                    Coin {
+                        denom: "denom2".to_string(),
+                        amount: 100,
+                        meta: None,
+                    },
+                ],
+            }],
+            memo: None,
+        };
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let account1 = balance_changes
+            .iter()
+            .find(|balance| balance.address == "account1")
+            .unwrap();
+        let amount_of = |denom: &str| {
+            account1
+                .coins
+                .iter()
+                .find(|coin| coin.denom == denom)
+                .map(|coin| coin.amount)
+        };
+        //denom1 incurs the 10% burn, denom2 is exempt
+        assert_eq!(amount_of("denom1"), Some(-110));
+        assert_eq!(amount_of("denom2"), Some(-100));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_processing_stats() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            result.stats,
+            ProcessingStats {
+                denoms_processed: 2,
+                //account1, account2, account_recipient & issuer_account_A
+                accounts_touched: 4,
+                //80 of denom1 and 1000 of denom2
+                total_burnt: 1080,
+                total_commission: 120,
+                rejected: 0,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_issuer_send_and_commission_accumulate() -> Result<(), Box<dyn Error>> {
+        let original_balances = vec![
+            Balance {
+                address: "issuer_account_A".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            },
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            },
+        ];
+        let definitions = vec![DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0_f64)
+            .with_commission_rate(0.1_f64)];
+        let multi_send = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    }],
+                },
+            ],
+            outputs: vec![
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 150,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account_recipient2".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 50,
+                        meta: None,
+                    }],
+                },
+            ],
+            memo: None,
+        };
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let amount_of = |address: &str| {
+            balance_changes
+                .iter()
+                .find(|balance| balance.address == address)
+                .map(|balance| balance.coins[0].amount)
+        };
+        //The issuer's exempt send of 100 nets against the 10 commission earned from account1
+        assert_eq!(amount_of("issuer_account_A"), Some(-100 + 10));
+        assert_eq!(amount_of("account1"), Some(-110));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_affected_addresses() -> Result<(), Box<dyn Error>> {
+        let (_, definitions, multi_send) = initialize_issuer_exists_on_sender_receiver();
+        let addresses = affected_addresses(&definitions, &multi_send)?;
+        assert_eq!(
+            addresses.into_iter().collect::<Vec<String>>(),
+            vec![
+                "account1",
+                "account2",
+                "account_recipient",
+                "issuer_account_A"
+            ]
+        );
+
+        //With no commission the issuer of denom1 is not touched
+        let (_, definitions, multi_send) = initialize_single_transfer_data();
+        assert!(!affected_addresses(&definitions, &multi_send)?.contains("issuer_account_A"));
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    //NOTE: Example #2 from README
+    pub fn test_prepared_tx_data() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let prepared = TxData::prepared(
+            multi_send.clone(),
+            original_balances.clone(),
+            definitions.clone(),
+        )?;
+
+        let mut manual = TxData::new(
+            multi_send,
+            Balances::try_from(original_balances)?,
+            definitions,
+        );
+        manual.initialize_definitions_map();
+        manual.initialize_bc_data()?;
+
+        assert_eq!(prepared.balances, manual.balances);
+        assert_eq!(prepared.denom_definitions_map, manual.denom_definitions_map);
+        assert_eq!(
+            prepared.non_issuer_input_sum_map,
+            manual.non_issuer_input_sum_map
+        );
+        assert_eq!(
+            prepared.non_issuer_output_sum_map,
+            manual.non_issuer_output_sum_map
+        );
+
+        //An invalid tx can't be prepared
+        let (original_balances, definitions, multi_send) = initialize_invalid_sum_data();
+        assert_eq!(
+            TxData::prepared(multi_send, original_balances, definitions).err(),
+            Some(TxError::InvalidSum)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #5 from README
+    pub fn test_rounding_gain() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_rounding_up_data();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        //Theoretical fees are 2 * (0.01 + 0.01) = 0.04, but each of the 4 shares is rounded up to 1
+        assert_eq!(result.rounding_gain.get("denom1"), Some(&4));
+
+        //No overshoot when every share is a whole number
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.rounding_gain.get("denom1"), Some(&0));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sub_unit_burn_rounding() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        definitions[0].burn_rate = 1e-9_f64;
+        multi_send.inputs[0].coins[0].amount = 10;
+        multi_send.outputs[0].coins[0].amount = 10;
+        let burn_with = |options: &TxOptions| {
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                options,
+            )
+            .map(|result| result.stats.total_burnt)
+        };
+
+        //By default a raw burn of 1e-8 is rounded up to a whole unit
+        assert_eq!(burn_with(&TxOptions::default())?, 1);
+        //A floor below the raw burn still rounds it up
+        let options = TxOptions {
+            min_burn_floor: Some(1e-9),
+            ..Default::default()
+        };
+        assert_eq!(burn_with(&options)?, 1);
+        //Below the floor the share is waived
+        let options = TxOptions {
+            min_burn_floor: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(burn_with(&options)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_memo_passthrough() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let with_memo = MultiSend {
+            memo: Some("invoice #42".to_string()),
+            ..multi_send.clone()
+        };
+
+        //No memo key is written when there is none
+        assert!(!serde_json::to_string(&multi_send)?.contains("memo"));
+        let round_tripped: MultiSend = serde_json::from_str(&serde_json::to_string(&with_memo)?)?;
+        assert_eq!(round_tripped, with_memo);
+
+        let expected =
+            calculate_balance_changes(original_balances.clone(), definitions.clone(), multi_send)?;
+        let actual = calculate_balance_changes(original_balances, definitions, round_tripped)?;
+        assert_eq!(normalize_balances(&actual), normalize_balances(&expected));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_cosmos_coin_json() -> Result<(), Box<dyn Error>> {
+        let coin = Coin {
+            denom: "denom1".to_string(),
+            amount: 1000,
+            meta: None,
+        };
+        assert_eq!(
+            coin.to_cosmos_coin_json(),
+            r#"{"denom":"denom1","amount":"1000"}"#
+        );
+
+        let large = Coin {
+            denom: "denom1".to_string(),
+            amount: i128::MAX - 1,
+            meta: None,
+        };
+        let parsed: serde_json::Value = serde_json::from_str(&large.to_cosmos_coin_json())?;
+        assert_eq!(
+            parsed["amount"].as_str().unwrap().parse::<i128>()?,
+            i128::MAX - 1
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_inputs_for_outputs() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        let gross = TxOptions {
+            amount_semantics: AmountSemantics::Gross,
+            ..Default::default()
+        };
+        let credited = |multi_send: MultiSend, options: &TxOptions| {
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send,
+                options,
+            )
+            .map(|result| {
+                result
+                    .balance_changes
+                    .into_iter()
+                    .find(|balance| balance.address == "account_recipient")
+                    .map(|balance| balance.coins[0].amount)
+            })
+        };
+        let gross_send = inputs_for_outputs(
+            &definitions,
+            multi_send.outputs.clone(),
+            vec!["account1".to_string()],
+            &gross,
+        )?;
+        //The recipient must be sent 112 gross so that 100 is left after the ceil(112 * 0.1) = 12 burnt
+        assert_eq!(gross_send.inputs[0].coins[0].amount, 112);
+        assert_eq!(credited(gross_send, &gross)?, Some(100));
+
+        //Net fees are paid on top, so the sender sends exactly the 100 to deliver
+        let net_send = inputs_for_outputs(
+            &definitions,
+            multi_send.outputs.clone(),
+            vec!["account1".to_string()],
+            &TxOptions::default(),
+        )?;
+        assert_eq!(net_send.inputs[0].coins[0].amount, 100);
+        assert_eq!(credited(net_send, &TxOptions::default())?, Some(100));
+
+        //No sender to fund the outputs
+        assert_eq!(
+            inputs_for_outputs(&definitions, multi_send.outputs, vec![], &gross).err(),
+            Some(TxError::NoInputs)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_missing_inputs_or_outputs() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        let no_outputs = MultiSend {
+            outputs: vec![],
+            ..multi_send.clone()
+        };
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                no_outputs
+            )
+            .err(),
+            Some(TxError::NoOutputs)
+        );
+
+        let no_inputs = MultiSend {
+            inputs: vec![],
+            ..multi_send
+        };
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, definitions, no_inputs).err(),
+            Some(TxError::NoInputs)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_effective_burn_rate() -> Result<(), Box<dyn Error>> {
+        let (_, definitions, multi_send) = initialize_issuer_exists_on_sender_receiver();
+        let rate = effective_burn_rate(&definitions, &multi_send, "denom1")?;
+        //Half of the outputs go to the issuer, so only 40 is burnt on the 1000 sent
+        assert!((rate - 0.04).abs() < 1e-9);
+        assert!(rate < definitions[0].burn_rate);
+        assert_eq!(
+            effective_burn_rate(&definitions, &multi_send, "denom3")?,
+            0_f64
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_validated_tx_computes_without_revalidating() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let validated = ValidatedTx::new(original_balances, definitions, multi_send)?;
+        assert_eq!(TxData::prepared_calls(), 1);
+
+        let simulated = validated.compute();
+        let committed = validated.compute();
+        assert_eq!(
+            normalize_balances(&simulated),
+            normalize_balances(&committed)
+        );
+        assert_eq!(TxData::prepared_calls(), 1);
+
+        //Insufficient balances are caught up front
+        let (original_balances, definitions, multi_send) = initialize_insufficient_balance_data();
+        assert!(ValidatedTx::new(original_balances, definitions, multi_send).is_err());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_issuer_sending_another_issuers_denom() -> Result<(), Box<dyn Error>> {
+        let original_balances = vec![Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom2".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        }];
+        let definitions = vec![
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0_f64)
+                .with_commission_rate(0_f64),
+            DenomDefinition::default()
+                .with_denom("denom2")
+                .with_issuer("issuer_account_B")
+                .with_burn_rate(0.1_f64)
+                .with_commission_rate(0.2_f64),
+        ];
+        let multi_send = MultiSend {
+            inputs: vec![Balance {
+                address: "issuer_account_A".to_string(),
+                coins: vec![Coin {
+                    denom: "denom2".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
+            }],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: "denom2".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
+            }],
+            memo: None,
+        };
+
+        let balance_changes = normalize_balances(&calculate_balance_changes(
+            original_balances,
+            definitions,
+            multi_send,
+        )?);
+        //Issuing denom1 does not exempt issuer_account_A from denom2's fees
+        assert_eq!(
+            balance_changes,
+            vec![
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom2".to_string(),
+                        amount: 100,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom2".to_string(),
+                        amount: -130,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_B".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom2".to_string(),
+                        amount: 20,
+                        meta: None,
+                    }],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_to_events() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let events = to_events(&result);
+
+        let burns = events
+            .iter()
+            .filter(|event| {
+                event.event_type == "burn" && event.attribute("denom") == Some("denom2")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(burns.len(), 1);
+        assert_eq!(burns[0].attribute("sender"), Some("account2"));
+        assert_eq!(burns[0].attribute("amount"), Some("1000"));
+
+        let commission = events
+            .iter()
+            .find(|event| event.event_type == "commission")
+            .unwrap();
+        assert_eq!(commission.attribute("recipient"), Some("issuer_account_A"));
+        assert_eq!(commission.attribute("amount"), Some("120"));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.event_type == "transfer")
+                .count(),
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_case_insensitive_denoms() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        multi_send.inputs[0].coins[0].denom = "DENOM1".to_string();
+        multi_send.outputs[0].coins[0].denom = " Denom1".to_string();
+
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::UnknownDenom {
+                denom: "DENOM1".to_string()
+            })
+        );
+
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions {
+                case_insensitive_denoms: true,
+                ..Default::default()
+            },
+        )?;
+        let account1 = result
+            .balance_changes
+            .iter()
+            .find(|balance| balance.address == "account1")
+            .unwrap();
+        assert_eq!(
+            account1.coins,
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -110,
+                meta: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_sorted_by_denom() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        multi_send.outputs[0].coins.reverse();
+        let sorted = multi_send.clone().sorted_by_denom();
+        assert_eq!(sorted.outputs[0].coins[0].denom, "denom1");
+
+        let unsorted_changes =
+            calculate_balance_changes(original_balances.clone(), definitions.clone(), multi_send)?;
+        let sorted_changes = calculate_balance_changes(original_balances, definitions, sorted)?;
+        assert_eq!(
+            normalize_balances(&unsorted_changes),
+            normalize_balances(&sorted_changes)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_filtered_denoms() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        //Unbalance denom2, which only matters when denom2 is processed
+        multi_send.outputs[0].coins[1].amount = 1;
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::InvalidSum)
+        );
+
+        let denoms = HashSet::from(["denom1".to_string()]);
+        let balance_changes = calculate_balance_changes_filtered(
+            original_balances,
+            definitions,
+            multi_send,
+            &denoms,
+        )?;
+        assert!(balance_changes
+            .iter()
+            .flat_map(|balance| balance.coins.iter())
+            .all(|coin| coin.denom == "denom1"));
+        assert!(!balance_changes
+            .iter()
+            .any(|balance| balance.address == "account2"));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_denom_definition_builder() -> Result<(), Box<dyn Error>> {
+        let built = DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0.08)
+            .with_commission_rate(0.12);
+        let (_, definitions, _) = initialize_no_issuer_on_sender_or_receiver();
+        assert_eq!(built, definitions[0]);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_max_entries() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_rounding_up_data();
+        //Split account1's input in two to get 3 inputs
+        multi_send.inputs[0].coins[0].amount = 2;
+        multi_send.inputs.push(Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 1,
+                meta: None,
+            }],
+        });
+        multi_send.outputs[0].coins[0].amount = 4;
+        let options = TxOptions {
+            max_inputs: Some(2),
+            max_outputs: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )
+            .err(),
+            Some(TxError::TooManyEntries {
+                kind: "inputs",
+                count: 3,
+                limit: 2
+            })
+        );
+
+        multi_send.inputs.pop();
+        multi_send.outputs[0].coins[0].amount = 3;
+        assert!(calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options
+        )
+        .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_rounding_across_many_denoms() -> Result<(), Box<dyn Error>> {
+        //account1 holds a one-third share of ten denoms, each rounding a fractional fee up
+        let mut original_balances = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![],
+            },
+        ];
+        let mut definitions = vec![];
+        let mut multi_send = MultiSend {
+            inputs: vec![],
+            outputs: vec![],
+            memo: None,
+        };
+        for i in 0..10 {
+            let denom = format!("denom{}", i);
+            for (balance, amount) in original_balances.iter_mut().zip([1, 2]) {
+                balance.coins.push(Coin {
+                    denom: denom.clone(),
+                    amount: 1000,
+                    meta: None,
+                });
+                multi_send.inputs.push(Balance {
+                    address: balance.address.clone(),
+                    coins: vec![Coin {
+                        denom: denom.clone(),
+                        amount,
+                        meta: None,
+                    }],
+                });
+            }
+            multi_send.outputs.push(Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: denom.clone(),
+                    amount: 3,
+                    meta: None,
+                }],
+            });
+            definitions.push(
+                DenomDefinition::default()
+                    .with_denom(&denom)
+                    .with_issuer("issuer_account_A")
+                    .with_burn_rate(0.01)
+                    .with_commission_rate(0.02),
+            );
+        }
+
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let rounded_shares: i128 = result
+            .fee_shares
+            .iter()
+            .filter(|share| share.address == "account1")
+            .map(|share| share.burn + share.commission)
+            .sum();
+        let debited: i128 = result
+            .balance_changes
+            .iter()
+            .filter(|balance| balance.address == "account1")
+            .flat_map(|balance| balance.coins.iter())
+            .map(|coin| -coin.amount)
+            .sum();
+
+        //1 burnt + 1 commission per denom, on top of the 10 sent
+        assert_eq!(rounded_shares, 20);
+        assert_eq!(debited - 10, rounded_shares);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_to_csv() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            to_csv(&balance_changes),
+            "address,denom,amount\n\
+             account1,denom1,-1200\n\
+             account2,denom2,-2000\n\
+             account_recipient,denom1,1000\n\
+             account_recipient,denom2,1000\n\
+             issuer_account_A,denom1,120\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_non_issuer_sending_only_to_issuer() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        //No non-issuer output, so the denom has no entry in non_issuer_output_sum_map
+        multi_send.outputs[0].address = "issuer_account_A".to_string();
+        let tx_data = TxData::prepared(
+            multi_send.clone(),
+            original_balances.clone(),
+            definitions.clone(),
+        )?;
+        assert!(!tx_data.non_issuer_output_sum_map.contains_key("denom1"));
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            normalize_balances(&balance_changes),
+            vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: -100,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    }],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_min_transfer_rejects_dust() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        multi_send.inputs[0].coins[0].amount = 1;
+        multi_send.outputs[0].coins[0].amount = 1;
+        let options = TxOptions {
+            min_transfer: HashMap::from([("denom1".to_string(), 10)]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )
+            .err(),
+            Some(TxError::DustOutput {
+                denom: "denom1".to_string(),
+                amount: 1
+            })
+        );
+        assert!(calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions::default()
+        )
+        .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_exclude_issuers_from_output() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let options = TxOptions {
+            exclude_issuers_from_output: true,
+            ..Default::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+
+        assert!(!result
+            .balance_changes
+            .iter()
+            .any(|balance| balance.address == "issuer_account_A"));
+        //The commission is still charged to the sender
+        assert!(result.balance_changes.contains(&Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -1200,
+                meta: None,
+            }],
+        }));
+        assert_eq!(result.stats.total_commission, 120);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_exclude_issuers_keeps_other_denoms() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        //The issuer of denom1 receives denom2 as an ordinary recipient
+        multi_send.outputs[0].coins.truncate(1);
+        multi_send.outputs.push(Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom2".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        });
+        let options = TxOptions {
+            exclude_issuers_from_output: true,
+            ..Default::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+
+        assert!(result.balance_changes.contains(&Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom2".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        }));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 and #5 from README
+    pub fn test_theoretical_fees() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let (burn, commission) = theoretical_fees(&definitions, &multi_send, "denom1")?;
+        //total_bc is min(1000, 1000), which divides evenly so nothing is lost to rounding
+        assert!((burn - 80_f64).abs() < 1e-9);
+        assert!((commission - 120_f64).abs() < 1e-9);
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.stats.total_burnt, 1080);
+
+        //Each sender's 0.01 share rounds up to 1, so 2 units are charged for a theoretical 0.02
+        let (original_balances, definitions, multi_send) = initialize_rounding_up_data();
+        let (burn, _) = theoretical_fees(&definitions, &multi_send, "denom1")?;
+        assert!((burn - 0.02).abs() < 1e-9);
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.stats.total_burnt, 2);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "node-import")]
+    pub fn test_from_node_snapshot() -> Result<(), Box<dyn Error>> {
+        let snapshot = r#"{
+            "balances": [
+                {
+                    "address": "devcore1sender",
+                    "coins": [
+                        {"denom": "ucore", "amount": "5000000"},
+                        {"denom": "utoken-devcore1issuer", "amount": "1000000"}
+                    ]
+                }
+            ],
+            "tokens": [
+                {
+                    "denom": "utoken-devcore1issuer",
+                    "issuer": "devcore1issuer",
+                    "symbol": "TOKEN",
+                    "burn_rate": "0.080000000000000000",
+                    "send_commission_rate": "0.120000000000000000"
+                }
+            ]
+        }"#;
+        let (balances, definitions) = from_node_snapshot(snapshot)?;
+        assert_eq!(balances[0].address, "devcore1sender");
+        assert_eq!(
+            balances[0].coins[1],
+            Coin {
+                denom: "utoken-devcore1issuer".to_string(),
+                amount: 1_000_000,
+                meta: None,
+            }
+        );
+        assert_eq!(
+            definitions,
+            vec![DenomDefinition::default()
+                .with_denom("utoken-devcore1issuer")
+                .with_issuer("devcore1issuer")
+                .with_burn_rate(0.08)
+                .with_commission_rate(0.12)]
+        );
+
+        let malformed = snapshot.replace("\"5000000\"", "\"5e6\"");
+        assert!(matches!(
+            from_node_snapshot(&malformed),
+            Err(TxError::InvalidSnapshot { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_issuer_not_exempt_from_burn() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        multi_send.inputs[0].address = "issuer_account_A".to_string();
+        let exempt = calculate_balance_changes(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+        )?;
+        assert!(exempt.contains(&Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -100,
+                meta: None,
+            }],
+        }));
+
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_issuer_exempt_burn(false))
+            .collect::<Vec<DenomDefinition>>();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        //100 sent and 10 burnt (burn_rate is 0.1)
+        assert!(result.balance_changes.contains(&Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -110,
+                meta: None,
+            }],
+        }));
+        assert_eq!(result.stats.total_burnt, 10);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sum_overflow() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        //i128::MAX + 2 wraps around to i128::MIN + 1 on both sides, so an unchecked sum would match
+        multi_send.inputs[0].coins[0].amount = i128::MAX;
+        multi_send.inputs[0].coins.push(Coin {
+            denom: "denom1".to_string(),
+            amount: 2,
+            meta: None,
+        });
+        multi_send.outputs[0].coins[0].amount = i128::MIN + 1;
+        assert_eq!(
+            multi_send.validate_multi_send_tx().err(),
+            Some(TxError::Overflow)
+        );
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send).err(),
+            Some(TxError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_sweep_rates() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let sweep = sweep_rates(
+            original_balances,
+            definitions,
+            multi_send,
+            "denom1",
+            &[0.0, 0.1, 0.2],
+        );
+        assert_eq!(sweep, vec![(0.0, 0), (0.1, 100), (0.2, 200)]);
+        assert!(sweep.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #5 from README
+    pub fn test_exact_total_tie_break() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_rounding_up_data();
+        let options = TxOptions {
+            share_rounding: ShareRounding::ExactTotal,
+            ..Default::default()
+        };
+        let mut reversed = multi_send.clone();
+        reversed.inputs.reverse();
+
+        //Both senders have a 0.01 share, so the single unit of burn and of commission goes to account1
+        for multi_send in [multi_send.clone(), reversed, multi_send] {
+            let result = calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send,
+                &options,
+            )?;
+            assert_eq!(
+                normalize_balances(&result.balance_changes),
+                normalize_balances(&[
+                    Balance {
+                        address: "account1".to_string(),
+                        coins: vec![Coin {
+                            denom: "denom1".to_string(),
+                            amount: -3,
+                            meta: None,
+                        }],
+                    },
+                    Balance {
+                        address: "account2".to_string(),
+                        coins: vec![Coin {
+                            denom: "denom1".to_string(),
+                            amount: -1,
+                            meta: None,
+                        }],
+                    },
+                    Balance {
+                        address: "account_recipient".to_string(),
+                        coins: vec![Coin {
+                            denom: "denom1".to_string(),
+                            amount: 2,
+                            meta: None,
+                        }],
+                    },
+                    Balance {
+                        address: "issuer_account_A".to_string(),
+                        coins: vec![Coin {
+                            denom: "denom1".to_string(),
+                            amount: 1,
+                            meta: None,
+                        }],
+                    },
+                ])
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_is_fee_free() -> Result<(), Box<dyn Error>> {
+        let (_, definitions, multi_send) = initialize_no_issuer_on_sender_or_receiver();
+        assert!(!is_fee_free(&definitions, &multi_send));
+
+        let zero_rates = definitions
+            .into_iter()
+            .map(|definition| definition.with_burn_rate(0_f64).with_commission_rate(0_f64))
+            .collect::<Vec<DenomDefinition>>();
+        assert!(is_fee_free(&zero_rates, &multi_send));
+
+        //The issuer sending its own token is exempt by default
+        let (_, definitions, mut multi_send) = initialize_single_transfer_data();
+        assert!(!is_fee_free(&definitions, &multi_send));
+        multi_send.inputs[0].address = "issuer_account_A".to_string();
+        assert!(is_fee_free(&definitions, &multi_send));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_tagged_changes() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let tags_of = |address: &str| {
+            result
+                .tagged_changes
+                .iter()
+                .filter(|tagged| tagged.address == address)
+                .map(|tagged| (tagged.denom.as_str(), tagged.amount, tagged.category))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            tags_of("issuer_account_A"),
+            vec![("denom1", 120, ChangeCategory::Commission)]
+        );
+        //account1's -1200 is split by cause
+        assert_eq!(
+            tags_of("account1"),
+            vec![
+                ("denom1", -1000, ChangeCategory::Transfer),
+                ("denom1", -80, ChangeCategory::Burn),
+                ("denom1", -120, ChangeCategory::Commission),
+            ]
+        );
+        //Summing the tags gives back the balance changes
+        let summed = result
+            .tagged_changes
+            .iter()
+            .map(|tagged| Balance {
+                address: tagged.address.clone(),
+                coins: vec![Coin {
+                    denom: tagged.denom.clone(),
+                    amount: tagged.amount,
+                    meta: None,
+                }],
+            })
+            .collect::<Vec<Balance>>();
+        assert_eq!(
+            normalize_balances(&summed),
+            normalize_balances(&result.balance_changes)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_many_senders() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_many_senders_data(1000);
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let changes = normalize_balances(&balance_changes);
+        assert_eq!(changes.len(), 1002);
+        //Each sender pays 10 + 5 burnt + 3 commission (2.5 rounded up)
+        assert!(changes
+            .iter()
+            .filter(|balance| balance.address.starts_with("sender"))
+            .all(|balance| balance.coins[0].amount == -18));
+        assert!(changes.contains(&Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 3000,
+                meta: None,
+            }],
+        }));
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    //Timing of a 50k entry tx, run with `cargo test --release -- --ignored --nocapture`
+    pub fn bench_many_senders() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_many_senders_data(50_000);
+        let start = std::time::Instant::now();
+        calculate_balance_changes(original_balances, definitions, multi_send)?;
+        println!("50k senders processed in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_presized_maps_match_growing() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_many_senders_data(1000);
+        let (presized, growing) = presized_and_growing(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            apply_tx(presized, &TxOptions::default(), None)?.coin_balance_changes_map,
+            apply_tx(growing, &TxOptions::default(), None)?.coin_balance_changes_map
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    //Timing of a 50k entry tx with the pre-sized maps of `TxData::new` against default-capacity maps
+    //growing as they are filled, run with `cargo test --release -- --ignored --nocapture`
+    pub fn bench_presized_maps() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_many_senders_data(50_000);
+        let (presized, growing) = presized_and_growing(original_balances, definitions, multi_send)?;
+
+        let start = std::time::Instant::now();
+        apply_tx(presized, &TxOptions::default(), None)?;
+        println!("50k senders with pre-sized maps in {:?}", start.elapsed());
+        let start = std::time::Instant::now();
+        apply_tx(growing, &TxOptions::default(), None)?;
+        println!("50k senders with growing maps in {:?}", start.elapsed());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_assert_burn_matches() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        assert_burn_matches(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            "denom1",
+            80,
+        )?;
+        assert_eq!(
+            assert_burn_matches(original_balances, definitions, multi_send, "denom1", 79).err(),
+            Some(TxError::BurnMismatch {
+                denom: "denom1".to_string(),
+                expected: 79,
+                computed: 80
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_indexed_multi_send() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let mut indexed = IndexedMultiSend {
+            inputs: vec![
+                IndexedInput {
+                    balance_index: 0,
+                    coins: multi_send.inputs[0].coins.clone(),
+                },
+                IndexedInput {
+                    balance_index: 1,
+                    coins: multi_send.inputs[1].coins.clone(),
+                },
+            ],
+            outputs: multi_send.outputs.clone(),
+        };
+
+        let converted = indexed.to_multi_send(&original_balances)?;
+        assert_eq!(converted, multi_send);
+        assert_eq!(
+            normalize_balances(&calculate_balance_changes(
+                original_balances.clone(),
+                definitions.clone(),
+                converted
+            )?),
+            normalize_balances(&calculate_balance_changes(
+                original_balances.clone(),
+                definitions,
+                multi_send
+            )?)
+        );
+
+        indexed.inputs[1].balance_index = 2;
+        assert_eq!(
+            indexed.to_multi_send(&original_balances).err(),
+            Some(TxError::InvalidBalanceIndex { index: 2 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_negative_rate_violates_invariant() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //Nothing rejects negative rates up front (the fee rate check is opt-in and only caps the top)
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_commission_rate(-0.5))
+            .collect::<Vec<DenomDefinition>>();
+        let result = calculate_balance_changes_detailed(original_balances, definitions, multi_send);
+        assert!(matches!(
+            result,
+            Err(TxError::InternalInvariant { reason }) if reason.contains("commission -50")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_balance_changes_map() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let map = calculate_balance_changes_map(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+        )?;
+        assert_eq!(map["issuer_account_A"]["denom1"], 560);
+
+        let from_map = map
+            .into_iter()
+            .map(|(address, coins)| Balance {
+                address,
+                coins: coins
+                    .into_iter()
+                    .map(|(denom, amount)| Coin {
+                        denom,
+                        amount,
+                        meta: None,
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<Balance>>();
+        assert_eq!(
+            normalize_balances(&from_map),
+            normalize_balances(&calculate_balance_changes(
+                original_balances,
+                definitions,
+                multi_send
+            )?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_zero_output_creates_no_entry() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        multi_send.outputs.push(Balance {
+            address: "account_fresh".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 0,
+                meta: None,
+            }],
+        });
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert!(!balance_changes
+            .iter()
+            .any(|balance| balance.address == "account_fresh"));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_recipient_credits() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let credits = recipient_credits(&multi_send);
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let received = balance_changes
+            .iter()
+            .filter(|balance| balance.address != "issuer_account_A")
+            .flat_map(|balance| {
+                balance
+                    .coins
+                    .iter()
+                    .filter(|coin| coin.amount > 0)
+                    .map(|coin| ((balance.address.clone(), coin.denom.clone()), coin.amount))
+            })
+            .collect::<HashMap<(String, String), i128>>();
+        assert_eq!(credits, received);
+        assert_eq!(
+            credits[&("account_recipient".to_string(), "denom2".to_string())],
+            1000
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_commission_split() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //A commission of 60 on the 100 sent
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_commission_rate(0.6))
+            .collect::<Vec<DenomDefinition>>();
+        let credit_of = |changes: &[Balance], address: &str| {
+            changes
+                .iter()
+                .find(|balance| balance.address == address)
+                .map(|balance| balance.coins[0].amount)
+        };
+
+        for (weights, expected) in [((0.5, 0.5), (30, 30)), ((0.333, 0.667), (20, 40))] {
+            let split = definitions
+                .iter()
+                .cloned()
+                .map(|definition| {
+                    definition.with_commission_split(vec![
+                        ("collector_1".to_string(), weights.0),
+                        ("collector_2".to_string(), weights.1),
+                    ])
+                })
+                .collect::<Vec<DenomDefinition>>();
+            let balance_changes =
+                calculate_balance_changes(original_balances.clone(), split, multi_send.clone())?;
+            assert_eq!(credit_of(&balance_changes, "collector_1"), Some(expected.0));
+            assert_eq!(credit_of(&balance_changes, "collector_2"), Some(expected.1));
+            assert_eq!(credit_of(&balance_changes, "issuer_account_A"), None);
+            //100 sent, 10 burnt and 60 commission
+            assert_eq!(credit_of(&balance_changes, "account1"), Some(-170));
+        }
+
+        let unbalanced = definitions
+            .into_iter()
+            .map(|definition| {
+                definition.with_commission_split(vec![("collector_1".to_string(), 0.5)])
+            })
+            .collect::<Vec<DenomDefinition>>();
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, unbalanced, multi_send).err(),
+            Some(TxError::InvalidCommissionSplit {
+                denom: "denom1".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_multiple_issuers() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| {
+                definition
+                    .with_commission_rate(0.1)
+                    .with_issuers(vec!["issuer_account_B".to_string()])
+            })
+            .collect::<Vec<DenomDefinition>>();
+        for sender in ["issuer_account_A", "issuer_account_B"] {
+            original_balances.push(Balance {
+                address: sender.to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            });
+            multi_send.inputs.push(Balance {
+                address: sender.to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
+            });
+        }
+        multi_send.outputs[0].coins[0].amount = 300;
+
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let change_of = |address: &str| {
+            balance_changes
+                .iter()
+                .find(|balance| balance.address == address)
+                .map(|balance| balance.coins[0].amount)
+        };
+        //Both issuers send fee free, account1 pays 10 burnt + 10 commission to issuer_account_A
+        assert_eq!(change_of("issuer_account_A"), Some(-90));
+        assert_eq!(change_of("issuer_account_B"), Some(-100));
+        assert_eq!(change_of("account1"), Some(-120));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_verify_output_sum() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let mut result = calculate_balance_changes_detailed(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+        )?;
+        verify_output_sum(&result, "denom1")?;
+        verify_output_sum(&result, "denom2")?;
+
+        //Fees deducted from the outputs break the invariant
+        let gross = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions {
+                amount_semantics: AmountSemantics::Gross,
+                ..Default::default()
+            },
+        )?;
+        assert!(matches!(
+            verify_output_sum(&gross, "denom1"),
+            Err(TxError::OutputSumMismatch { expected: 1000, .. })
+        ));
+
+        result.tagged_changes.retain(|tagged| tagged.amount < 0);
+        assert_eq!(
+            verify_output_sum(&result, "denom1").err(),
+            Some(TxError::OutputSumMismatch {
+                denom: "denom1".to_string(),
+                expected: 1000,
+                credited: 0
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_verify_result_within() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        //The other side charged account1 one unit less
+        let mut expected = result.balance_changes.clone();
+        for balance in expected.iter_mut() {
+            if balance.address == "account1" {
+                balance.coins[0].amount += 1;
+            }
+        }
+
+        assert_eq!(result.verify_result_within(&expected, 1), Ok(()));
+        assert_eq!(
+            result.verify_result_within(&expected, 0),
+            Err(vec![ResultDeviation {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                expected: -1199,
+                actual: -1200,
+            }])
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_no_definitions() -> Result<(), Box<dyn Error>> {
+        let (original_balances, _, multi_send) = initialize_single_transfer_data();
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, vec![], multi_send).err(),
+            Some(TxError::NoDefinitions)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_max_account_debit() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        assert_eq!(
+            max_account_debit(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                "denom1"
+            )?,
+            ("account1".to_string(), 715)
+        );
+        assert_eq!(
+            max_account_debit(original_balances, definitions, multi_send, "denom2").err(),
+            Some(TxError::NoInputs)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_to_cosmos_json() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        let json = to_cosmos_json(&balance_changes);
+
+        assert_eq!(json.as_array().map(Vec::len), Some(4));
+        assert_eq!(
+            json[0],
+            serde_json::json!({
+                "address": "account1",
+                "coins": [{"denom": "denom1", "amount": "-1200"}],
+            })
+        );
+        assert_eq!(
+            json[2]["coins"],
+            serde_json::json!([
+                {"denom": "denom1", "amount": "1000"},
+                {"denom": "denom2", "amount": "1000"},
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_deterministic_output() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        //Every run builds fresh maps, each with its own random hash seed, so anything iterating a
+        //HashMap into the result shows up as a changed line
+        let run = || -> Result<String, Box<dyn Error>> {
+            let result = calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+            )?;
+            Ok(format!(
+                "{}\n{:#?}\n{:#?}\n{:#?}",
+                serde_json::to_string_pretty(&result.balance_changes)?,
+                result.fee_shares,
+                result.tagged_changes,
+                to_ledger_entries(&result),
+            ))
+        };
+
+        let first = run()?;
+        for attempt in 1..200 {
+            let output = run()?;
+            if output != first {
+                let diff = first
+                    .lines()
+                    .zip(output.lines())
+                    .enumerate()
+                    .filter(|(_, (expected, actual))| expected != actual)
+                    .map(|(line, (expected, actual))| {
+                        format!("line {}:\n-{}\n+{}", line + 1, expected, actual)
+                    })
+                    .collect::<Vec<String>>();
+                panic!(
+                    "Run {} differs from the first run, the output depends on iteration order:\n{}",
+                    attempt,
+                    diff.join("\n")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_flat_fees() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_flat_burn_fee(5))
+            .collect::<Vec<DenomDefinition>>();
+        let single_send = multi_send.clone();
+        //Split the send in two, the flat burn is still charged once
+        multi_send.inputs[0].coins[0].amount = 60;
+        multi_send.inputs.push(Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 40,
+                meta: None,
+            }],
+        });
+
+        let result = calculate_balance_changes_detailed(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send,
+        )?;
+        //100 sent, 10 burnt by rate and 5 flat
+        assert!(result.balance_changes.contains(&Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -115,
+                meta: None,
+            }],
+        }));
+        assert_eq!(result.stats.total_burnt, 15);
+
+        //The sufficiency check covers the flat fee: 114 is enough for the rate-based fees only
+        original_balances[0].coins[0].amount = 114;
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, definitions, single_send).err(),
+            Some(TxError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                amount: 100,
+                fees: 15,
+                available: 114,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_empties_account() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        assert!(empties_account(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone()
+        )
+        .is_empty());
+
+        //909 sent + 91 burnt (909 * 0.1 rounded up) is all of account1's 1000
+        multi_send.inputs[0].coins[0].amount = 909;
+        multi_send.outputs[0].coins[0].amount = 909;
+        assert_eq!(
+            empties_account(original_balances, definitions, multi_send),
+            vec![("account1".to_string(), "denom1".to_string())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_issuer_send_generates_no_commission() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_commission_rate(0.2))
+            .collect::<Vec<DenomDefinition>>();
+        original_balances[0].address = "issuer_account_A".to_string();
+        multi_send.inputs[0].address = "issuer_account_A".to_string();
+
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.stats.total_commission, 0);
+        assert_eq!(
+            normalize_balances(&result.balance_changes),
+            vec![
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 100,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: -100,
+                        meta: None,
+                    }],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_denoms_by_burn() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        assert_eq!(
+            denoms_by_burn(original_balances, definitions, multi_send),
+            vec![("denom2".to_string(), 1000), ("denom1".to_string(), 80)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_commission_without_burn() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        let definitions = definitions
+            .into_iter()
+            .map(|definition| definition.with_burn_rate(0_f64).with_commission_rate(0.15))
+            .collect::<Vec<DenomDefinition>>();
+        original_balances[0].coins[0].amount = 2000;
+        multi_send.inputs[0].coins[0].amount = 1000;
+        multi_send.outputs[0].coins[0].amount = 1000;
+
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let mut expected = HashMap::new();
+        expected.insert(
+            "account1".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: -1150,
+                meta: None,
+            }],
+        );
+        expected.insert(
+            "issuer_account_A".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 150,
+                meta: None,
+            }],
+        );
+        expected.insert(
+            "account_recipient".to_string(),
+            vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        );
+        assert_changes_eq(&result.balance_changes, &expected);
+        assert_eq!(result.stats.total_burnt, 0);
+        assert_eq!(result.stats.total_commission, 150);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_balances_credit_debit() -> Result<(), Box<dyn Error>> {
+        let mut balances = Balances::default();
+        assert_eq!(balances.get("account1", "denom1"), 0);
+
+        balances.credit("account1", "denom1", 1000)?;
+        balances.debit("account1", "denom1", 250)?;
+        balances.debit("account2", "denom2", 10)?;
+        assert_eq!(balances.get("account1", "denom1"), 750);
+        assert_eq!(balances.get("account1", "denom2"), 0);
+        assert_eq!(balances.get("account2", "denom2"), -10);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_balances_round_trip() -> Result<(), Box<dyn Error>> {
+        let (original_balances, _, _) = initialize_no_issuer_on_sender_or_receiver();
+        let balances = Balances::try_from(original_balances.clone())?;
+        assert_eq!(balances.get("account1", "denom1"), 1_000_000);
+        assert_eq!(
+            BalanceStore::get(&balances, "account2", "denom2")?,
+            1_000_000
+        );
+        assert_eq!(balances.into_vec(), normalize_balances(&original_balances));
+
+        //Repeated entries for an address are summed
+        let split = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 600,
+                    meta: None,
+                }],
+            },
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 400,
+                    meta: None,
+                }],
+            },
+        ];
+        assert_eq!(Balances::try_from(split)?.get("account1", "denom1"), 1000);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #3 from README
+    pub fn test_explain_insufficient_balance() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_insufficient_balance_data();
+        let err = calculate_balance_changes_detailed(original_balances, definitions, multi_send)
+            .err()
+            .ok_or("the tx should be rejected")?;
+        assert_eq!(
+            err.explain(),
+            "Account 'account1' tried to send 350 denom1 plus 0 in fees, but only holds 0. The transaction needs 350 denom1 from this account."
+        );
+        //Variants without a dedicated explanation fall back on the Display form
+        assert_eq!(
+            TxError::Overflow.explain(),
+            "Multi Send Tx amounts overflow."
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README, without fees
+    pub fn test_fee_free_fast_path() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, mut multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        definitions[0] = definitions[0]
+            .clone()
+            .with_burn_rate(0_f64)
+            .with_commission_rate(0_f64);
+        //The issuer sends too, and is exempt from the fees it would otherwise pay
+        multi_send.inputs.push(Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 100,
+                meta: None,
+            }],
+        });
+        multi_send.outputs[0].coins[0].amount += 100;
+
+        for amount_semantics in [AmountSemantics::Net, AmountSemantics::Gross] {
+            let options = TxOptions {
+                amount_semantics,
+                ..TxOptions::default()
+            };
+            let (fast, general) = run_both_paths(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )?;
+            assert_eq!(fast.balance_changes, general.balance_changes);
+            assert_eq!(fast.share_trace, general.share_trace);
+            assert_eq!(fast.fee_shares, general.fee_shares);
+            assert_eq!(fast.tagged_changes, general.tagged_changes);
+            assert_eq!(fast.stats, general.stats);
+            assert_eq!(fast.rounding_gain, general.rounding_gain);
+        }
+
+        //A flat fee takes the tx off the fast path
+        definitions[0] = definitions[0].clone().with_flat_burn_fee(1);
+        let tx_data = TxData::prepared(multi_send, original_balances, definitions)?;
+        assert!(!tx_data.fee_free);
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    //Timing of a fee-free 50k entry tx on both paths, run with `cargo test --release -- --ignored --nocapture`
+    pub fn bench_fee_free_fast_path() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) = initialize_many_senders_data(50_000);
+        definitions[0] = definitions[0]
+            .clone()
+            .with_burn_rate(0_f64)
+            .with_commission_rate(0_f64);
+        let tx_data = TxData::prepared(multi_send, original_balances, definitions)?;
+        let general = general_path(tx_data.clone());
+
+        let start = std::time::Instant::now();
+        run_tx(tx_data, &TxOptions::default(), None)?;
+        println!(
+            "fee-free 50k senders on the fast path in {:?}",
+            start.elapsed()
+        );
+        let start = std::time::Instant::now();
+        run_tx(general, &TxOptions::default(), None)?;
+        println!(
+            "fee-free 50k senders on the general path in {:?}",
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_fee_incidence() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let incidence = fee_incidence(original_balances, definitions, multi_send);
+        //26 burnt and 39 commission on the 650 sent
+        assert_eq!(
+            incidence[&("account1".to_string(), "denom1".to_string())],
+            (26 + 39) as f64 / 650_f64
+        );
+        assert_eq!(
+            incidence[&("account2".to_string(), "denom1".to_string())],
+            (14 + 21) as f64 / 350_f64
+        );
+        assert_eq!(incidence.len(), 2);
+
+        //A rejected tx charges nothing
+        let (original_balances, definitions, multi_send) = initialize_insufficient_balance_data();
+        assert!(fee_incidence(original_balances, definitions, multi_send).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README, with every output going to the issuer
+    pub fn test_all_outputs_to_issuer_charge_no_fees() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        multi_send.inputs.truncate(1);
+        multi_send.outputs = vec![Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        }];
+
+        //non_issuer_output_sum is 0, so total_bc is 0 despite the non-issuer sender
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.stats.total_burnt, 0);
+        assert_eq!(result.stats.total_commission, 0);
+        assert_eq!(
+            normalize_balances(&result.balance_changes),
+            vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: -1000,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 1000,
+                        meta: None,
+                    }],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_empty_issuer_rejected() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        definitions[1].issuer = String::new();
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::EmptyField { field: "issuer" })
+        );
+
+        definitions[1].issuer = "issuer_account_B".to_string();
+        definitions[1].issuers = vec![String::new()];
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send).err(),
+            Some(TxError::EmptyField { field: "issuers" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_live_tx() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let mut live = LiveTx::new(original_balances.clone(), definitions.clone());
+
+        assert_eq!(
+            live.add_input(multi_send.inputs[0].clone())?,
+            vec![Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: -1000,
+                    meta: None,
+                }],
+            }]
+        );
+        live.add_input(multi_send.inputs[1].clone())?;
+        live.add_output(multi_send.outputs[0].clone())?;
+        //No fee is charged before the tx is finalized
+        assert_eq!(
+            live.running_changes(),
+            vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: -1000,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom2".to_string(),
+                        amount: -1000,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![
+                        Coin {
+                            denom: "denom1".to_string(),
+                            amount: 1000,
+                            meta: None,
+                        },
+                        Coin {
+                            denom: "denom2".to_string(),
+                            amount: 1000,
+                            meta: None,
+                        },
+                    ],
+                },
+            ]
+        );
+
+        let batch = calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            normalize_balances(&live.finalize()?.balance_changes),
+            normalize_balances(&batch)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_amount_str_round_trip() -> Result<(), Box<dyn Error>> {
+        for amount in [i128::MAX, i128::MIN, -1200] {
+            let coin = Coin {
+                denom: "denom1".to_string(),
+                amount,
+                meta: None,
+            };
+            assert_eq!(Coin::from_amount_str("denom1", &coin.amount_str())?, coin);
+        }
+        assert_eq!(
+            Coin {
+                denom: "denom1".to_string(),
+                amount: -1200,
+                meta: None,
+            }
+            .amount_str(),
+            "-1200"
+        );
+        assert_eq!(
+            Coin::from_amount_str("denom1", "12.5").err(),
+            Some(TxError::InvalidAmount {
+                denom: "denom1".to_string(),
+                amount: "12.5".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_audit_log() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let options = TxOptions {
+            audit: true,
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &options,
+        )?;
+        let mutation = |address: &str, denom: &str, delta: i128, reason: ChangeCategory| Mutation {
+            address: address.to_string(),
+            denom: denom.to_string(),
+            delta,
+            reason,
+        };
+        assert_eq!(
+            result.audit,
+            vec![
+                mutation("account1", "denom1", -1000, ChangeCategory::Transfer),
+                mutation("account1", "denom1", -80, ChangeCategory::Burn),
+                mutation("account1", "denom1", -120, ChangeCategory::Commission),
+                mutation(
+                    "issuer_account_A",
+                    "denom1",
+                    120,
+                    ChangeCategory::Commission
+                ),
+                mutation("account2", "denom2", -1000, ChangeCategory::Transfer),
+                mutation("account2", "denom2", -1000, ChangeCategory::Burn),
+                mutation(
+                    "account_recipient",
+                    "denom1",
+                    1000,
+                    ChangeCategory::Transfer
+                ),
+                mutation(
+                    "account_recipient",
+                    "denom2",
+                    1000,
+                    ChangeCategory::Transfer
+                ),
+            ]
+        );
+
+        //Replaying the log gives the final balances
+        let mut replayed = BTreeMap::new();
+        for mutation in result.audit.iter() {
+            *replayed
+                .entry((mutation.address.clone(), mutation.denom.clone()))
+                .or_insert(0) += mutation.delta;
+        }
+        for balance in result.balance_changes.iter() {
+            for coin in balance.coins.iter() {
+                assert_eq!(
+                    replayed[&(balance.address.clone(), coin.denom.clone())],
+                    coin.amount
+                );
+            }
+        }
+
+        //Off by default
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert!(result.audit.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_without_sender() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let balance_changes =
+            without_sender(original_balances, definitions, multi_send, "account2")?;
+        //The outputs shrink to 325 each, so account1 alone pays on total_bc = min(650, 325):
+        //26 burnt (325 * 0.08) and 39 commission (325 * 0.12)
+        assert_eq!(
+            normalize_balances(&balance_changes),
+            vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: -715,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "account_recipient".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 325,
+                        meta: None,
+                    }],
+                },
+                Balance {
+                    address: "issuer_account_A".to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount: 364,
+                        meta: None,
+                    }],
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_without_sender_large_amounts() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            mul_div_floor(i128::MAX, i128::MAX - 1, i128::MAX)?,
+            i128::MAX - 1
+        );
+        assert_eq!(mul_div_floor(i128::MAX, 3, 2), Err(TxError::Overflow));
+        //18-decimal amounts, where an output times the kept amount no longer fits an i128
+        let (_, definitions, _) = initialize_single_transfer_data();
+        let coin = |amount: i128| Coin {
+            denom: "denom1".to_string(),
+            amount,
+            meta: None,
+        };
+        let balance = |address: &str, amount: i128| Balance {
+            address: address.to_string(),
+            coins: vec![coin(amount)],
+        };
+        let original_balances = vec![
+            balance("account1", 10_i128.pow(21)),
+            balance("account2", 10_i128.pow(21)),
+        ];
+        let multi_send = MultiSend {
+            inputs: vec![
+                balance("account1", 10_i128.pow(20)),
+                balance("account2", 10_i128.pow(20)),
+            ],
+            outputs: vec![
+                balance("account3", 15 * 10_i128.pow(19) + 1),
+                balance("account4", 5 * 10_i128.pow(19) - 1),
+            ],
+            memo: None,
+        };
+        let balance_changes = without_sender(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send,
+            "account2",
+        )?;
+
+        //The outputs halve, floored to 75e18 and 25e18 - 1, and the leftover unit goes to the first
+        let expected = calculate_balance_changes(
+            original_balances,
+            definitions,
+            MultiSend {
+                inputs: vec![balance("account1", 10_i128.pow(20))],
+                outputs: vec![
+                    balance("account3", 75 * 10_i128.pow(18) + 1),
+                    balance("account4", 25 * 10_i128.pow(18) - 1),
+                ],
+                memo: None,
+            },
+        )?;
+        assert_eq!(
+            normalize_balances(&balance_changes),
+            normalize_balances(&expected)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_burn_only_issuer_absent() -> Result<(), Box<dyn Error>> {
+        //burn_rate 0.1 and commission_rate 0, so the issuer earns nothing
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        let tx_data = TxData::prepared(
+            multi_send.clone(),
+            original_balances.clone(),
+            definitions.clone(),
+        )?;
+        let tx_data = apply_tx(tx_data, &TxOptions::default(), None)?;
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(result.stats.total_burnt, 10);
+        assert!(!tx_data
+            .coin_balance_changes_map
+            .contains_key("issuer_account_A"));
+        assert!(result
+            .balance_changes
+            .iter()
+            .all(|balance| balance.address != "issuer_account_A"));
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_preview() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_issuer_exists_on_sender_receiver();
+        let preview = preview(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+        )?;
+        assert_eq!(
+            normalize_balances(&preview.deltas),
+            normalize_balances(&calculate_balance_changes(
+                original_balances.clone(),
+                definitions,
+                multi_send
+            )?)
+        );
+
+        let original = Balances::try_from(original_balances)?;
+        let deltas = Balances::try_from(preview.deltas)?;
+        for balance in preview.final_balances.iter() {
+            for coin in balance.coins.iter() {
+                assert_eq!(
+                    coin.amount,
+                    original.get(&balance.address, &coin.denom)
+                        + deltas.get(&balance.address, &coin.denom)
+                );
+            }
+        }
+        assert_eq!(
+            Balances::try_from(preview.final_balances)?.get("account1", "denom1"),
+            1_000_000 - 715
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_large_burn_fraction_warning() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        //80 denom1 burnt: 1.6% of 5000, 0.8% of 10000
+        definitions[0] = definitions[0].clone().with_total_supply(5000);
+        let result = calculate_balance_changes_detailed(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+        )?;
+        assert_eq!(
+            result.warnings,
+            vec![TxWarning::LargeBurnFraction {
+                denom: "denom1".to_string(),
+                fraction: 0.016,
+            }]
+        );
+
+        definitions[0] = definitions[0].clone().with_total_supply(10_000);
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert!(result.warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_calculate_from_rows() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let rows = vec![
+            ("account1".to_string(), "denom1".to_string(), 1_000_000),
+            ("account2".to_string(), "denom2".to_string(), 1_000_000),
+        ];
+        assert_eq!(
+            normalize_balances(&calculate_from_rows(
+                rows,
+                definitions.clone(),
+                multi_send.clone()
+            )?),
+            normalize_balances(&calculate_balance_changes(
+                original_balances,
+                definitions,
+                multi_send
+            )?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Definitions of example #1 from README
+    pub fn test_duplicate_input_address_checked_per_denom() -> Result<(), Box<dyn Error>> {
+        let (_, definitions, _) = initialize_no_issuer_on_sender_or_receiver();
+        let coin = |denom: &str, amount: i128| Coin {
+            denom: denom.to_string(),
+            amount,
+            meta: None,
+        };
+        let mut original_balances = vec![Balance {
+            address: "account1".to_string(),
+            coins: vec![coin("denom1", 1000), coin("denom2", 150)],
+        }];
+        //account1 appears in two inputs, each sending a different denom
+        let multi_send = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![coin("denom1", 100)],
+                },
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![coin("denom2", 100)],
+                },
+            ],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![coin("denom1", 100), coin("denom2", 100)],
+            }],
+            memo: None,
+        };
+
+        //denom2 burns at rate 1, so its 200 debit is checked against the 150 of denom2
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom2".to_string(),
+                amount: 100,
+                fees: 100,
+                available: 150,
+            })
+        );
+
+        original_balances[0].coins[1].amount = 200;
+        let balance_changes =
+            calculate_balance_changes(original_balances.clone(), definitions.clone(), multi_send)?;
+        assert!(normalize_balances(&balance_changes).contains(&Balance {
+            address: "account1".to_string(),
+            coins: vec![coin("denom1", -120), coin("denom2", -200)],
+        }));
+
+        //Two sends of the same denom are checked together: each debits 60 + 5 burnt + 8 commission,
+        //which 100 covers once but not twice
+        original_balances[0].coins[0].amount = 100;
+        let multi_send = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![coin("denom1", 60)],
+                },
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![coin("denom1", 60)],
+                },
+            ],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![coin("denom1", 120)],
+            }],
+            memo: None,
+        };
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::InsufficientBalance {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+                amount: 120,
+                fees: 26,
+                available: 100,
+            })
+        );
+
+        original_balances[0].coins[0].amount = 146;
+        let balance_changes =
+            calculate_balance_changes(original_balances, definitions, multi_send)?;
+        assert!(normalize_balances(&balance_changes).contains(&Balance {
+            address: "account1".to_string(),
+            coins: vec![coin("denom1", -146)],
+        }));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_ceil_epsilon() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_float_error_data();
+        let burn_shares = |ceil_epsilon: f64| -> Result<Vec<i128>, TxError> {
+            let options = TxOptions {
+                ceil_epsilon,
+                ..TxOptions::default()
+            };
+            let result = calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )?;
+            Ok(result.fee_shares.iter().map(|share| share.burn).collect())
+        };
+
+        //account1's share is 330 * 0.02 * 300 / 330, which comes out as 6.000000000000001
+        assert_eq!(burn_shares(0_f64)?, vec![7, 1]);
+        assert_eq!(burn_shares(1e-9)?, vec![6, 1]);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_collect_timings() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_many_senders_data(10_000);
+        let options = TxOptions {
+            collect_timings: true,
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &options,
+        )?;
+        let timings = result.timings.ok_or("timings were requested")?;
+        for time in [
+            timings.validation,
+            timings.bc_init,
+            timings.input_loop,
+            timings.output_loop,
+            timings.collect,
+        ] {
+            assert!(time > Duration::ZERO);
+        }
+        assert_eq!(timings.to_folded().lines().count(), 5);
+        assert!(timings.to_folded().starts_with("process_tx;validation "));
+
+        //Off by default
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert!(result.timings.is_none());
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Definitions of example #1 from README
+    pub fn test_issuer_net_change() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        multi_send.inputs[1] = Balance {
+            address: "issuer_account_A".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 200,
+                meta: None,
+            }],
+        };
+        multi_send.outputs[0].coins = vec![Coin {
+            denom: "denom1".to_string(),
+            amount: 1200,
+            meta: None,
+        }];
+
+        //120 commission earned on account1's 1000, less the 200 sent
+        assert_eq!(
+            issuer_net_change(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                "issuer_account_A"
+            ),
+            HashMap::from([("denom1".to_string(), 120 - 200)])
+        );
+        //issuer_account_B's denom isn't moved
+        assert!(issuer_net_change(
+            original_balances,
+            definitions,
+            multi_send,
+            "issuer_account_B"
+        )
+        .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_max_transfer_rejects_large_output() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        multi_send.inputs[0].coins[0].amount = 2000;
+        multi_send.outputs[0].coins[0].amount = 2000;
+        let options = TxOptions {
+            max_transfer: HashMap::from([("denom1".to_string(), 1000)]),
+            ..TxOptions::default()
+        };
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options
+            )
+            .err(),
+            Some(TxError::TransferCapExceeded {
+                denom: "denom1".to_string(),
+                amount: 2000,
+                cap: 1000,
+            })
+        );
+
+        //An output below the cap goes through
+        multi_send.inputs[0].coins[0].amount = 900;
+        multi_send.outputs[0].coins[0].amount = 900;
+        calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_validate_structure() -> Result<(), Box<dyn Error>> {
+        let (_, _, mut multi_send) = initialize_no_issuer_on_sender_or_receiver();
+        multi_send.validate_structure()?;
+
+        //The totals still match, but denom1 and denom2 don't balance on their own
+        let mut mismatched = multi_send.clone();
+        mismatched.outputs[0].coins[0].amount = 1500;
+        mismatched.outputs[0].coins[1].amount = 500;
+        assert_eq!(mismatched.validate_structure(), Err(TxError::InvalidSum));
+
+        let mut negative = multi_send.clone();
+        negative.inputs[0].coins[0].amount = -1000;
+        negative.outputs[0].coins[0].amount = -1000;
+        assert_eq!(
+            negative.validate_structure(),
+            Err(TxError::NegativeAmount {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+            })
+        );
+
+        //No definitions are known, so an undefined denom passes
+        for balance in multi_send
+            .inputs
+            .iter_mut()
+            .chain(multi_send.outputs.iter_mut())
+        {
+            for coin in balance.coins.iter_mut() {
+                coin.denom = format!("undefined_{}", coin.denom);
+            }
+        }
+        multi_send.validate_structure()?;
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_apply_batch_reject_hook() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //account1 holds 1000 and each send debits 110, the second one can't be covered
+        let mut large = multi_send.clone();
+        large.inputs[0].coins[0].amount = 850;
+        large.outputs[0].coins[0].amount = 850;
+        let txs = vec![multi_send.clone(), large];
+
+        let mut rejected = vec![];
+        let result = apply_batch_with_reject_hook(
+            original_balances.clone(),
+            definitions.clone(),
+            txs,
+            |index, err| rejected.push((index, err.clone())),
+        );
+        let expected = TxError::InsufficientBalance {
+            address: "account1".to_string(),
+            denom: "denom1".to_string(),
+            amount: 850,
+            fees: 85,
+            available: 890,
+        };
+        assert_eq!(result.err(), Some(expected.clone()));
+        assert_eq!(rejected, vec![(1, expected)]);
+
+        //Without a rejection the balances carry over between the txs
+        let final_balances = apply_batch(
+            original_balances,
+            definitions,
+            vec![multi_send.clone(), multi_send],
+        )?;
+        assert_eq!(
+            Balances::try_from(final_balances)?.get("account1", "denom1"),
+            1000 - 2 * 110
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_apply_batch_skipping_rejected() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //The large send can't be covered after the first one, so it is skipped and the last one applies
+        let mut large = multi_send.clone();
+        large.inputs[0].coins[0].amount = 850;
+        large.outputs[0].coins[0].amount = 850;
+        let txs = vec![multi_send.clone(), large, multi_send];
+
+        let (final_balances, stats) =
+            apply_batch_skipping_rejected(original_balances, definitions, txs)?;
+        assert_eq!(
+            Balances::try_from(final_balances)?.get("account1", "denom1"),
+            1000 - 2 * 110
+        );
+        assert_eq!(
+            stats,
+            ProcessingStats {
+                denoms_processed: 1,
+                //account1 & account_recipient, the issuer earns no commission
+                accounts_touched: 2,
+                total_burnt: 20,
+                total_commission: 0,
+                rejected: 1,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rational")]
+    pub fn test_rational_rates_match_decimal() -> Result<(), Box<dyn Error>> {
+        let examples = [
+            initialize_no_issuer_on_sender_or_receiver(),
+            initialize_issuer_exists_on_sender_receiver(),
+            initialize_rounding_up_data(),
+        ];
+        let rates = HashMap::from([
+            (
+                "denom1".to_string(),
+                RationalRate::new((8, 100), (12, 100))?,
+            ),
+            ("denom2".to_string(), RationalRate::new((1, 1), (0, 1))?),
+        ]);
+        for (index, (original_balances, definitions, multi_send)) in
+            examples.into_iter().enumerate()
+        {
+            //Example #5 charges 1% of each
+            let rates = match index {
+                2 => {
+                    HashMap::from([("denom1".to_string(), RationalRate::new((1, 100), (1, 100))?)])
+                }
+                _ => rates.clone(),
+            };
+            let options = TxOptions {
+                rational_rates: rates,
+                ..TxOptions::default()
+            };
+            let rational = calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone(),
+                &options,
+            )?;
+            let decimal =
+                calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+            assert_eq!(
+                normalize_balances(&rational.balance_changes),
+                normalize_balances(&decimal.balance_changes)
+            );
+            assert_eq!(rational.fee_shares, decimal.fee_shares);
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rational")]
+    pub fn test_rational_rates_avoid_float_error() -> Result<(), Box<dyn Error>> {
+        //330 * 0.02 * 300 / 330 comes out as 6.000000000000001 in floats, and exactly 6 here
+        let (original_balances, definitions, multi_send) = initialize_float_error_data();
+        let options = TxOptions {
+            rational_rates: HashMap::from([(
+                "denom1".to_string(),
+                RationalRate::new((2, 100), (0, 1))?,
+            )]),
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+        assert_eq!(
+            result
+                .fee_shares
+                .iter()
+                .map(|share| share.burn)
+                .collect::<Vec<i128>>(),
+            vec![6, 1]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rational")]
+    pub fn test_rational_rate_zero_denominator() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            RationalRate::new((1, 0), (0, 1)).err(),
+            Some(TxError::ZeroRateDenominator)
+        );
+        assert_eq!(
+            RationalRate::new((1, 100), (1, 0)).err(),
+            Some(TxError::ZeroRateDenominator)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rational")]
+    pub fn test_rational_rates_decide_charges() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) = initialize_single_transfer_data();
+        //Zero float rates would make the tx fee-free, but the exact rates charge 10% of each
+        definitions[0].burn_rate = 0_f64;
+        let exact = |burn: (i128, i128)| -> Result<TxOptions, TxError> {
+            Ok(TxOptions {
+                rational_rates: HashMap::from([(
+                    "denom1".to_string(),
+                    RationalRate::new(burn, (1, 10))?,
+                )]),
+                ..TxOptions::default()
+            })
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &exact((1, 10))?,
+        )?;
+        assert_eq!(result.stats.total_burnt, 10);
+        assert_eq!(result.stats.total_commission, 10);
+
+        //ExactTotal doesn't replace the exact shares
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &TxOptions {
+                share_rounding: ShareRounding::ExactTotal,
+                ..exact((1, 10))?
+            },
+        )?;
+        assert_eq!(result.stats.total_burnt, 10);
+
+        //An exact share too large for an i128 is an overflow rather than a saturated amount
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances,
+                definitions,
+                multi_send,
+                &exact((i128::MAX, 1))?,
+            )
+            .err(),
+            Some(TxError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_chunk_multi_send() -> Result<(), Box<dyn Error>> {
+        let coin = |denom: &str, amount: i128| Coin {
+            denom: denom.to_string(),
+            amount,
+            meta: None,
+        };
+        let balance = |address: &str, coins: Vec<Coin>| Balance {
+            address: address.to_string(),
+            coins,
+        };
+        let multi_send = MultiSend {
+            inputs: vec![
+                balance("account1", vec![coin("denom1", 650), coin("denom2", 100)]),
+                balance("account2", vec![coin("denom1", 350)]),
+                balance("account3", vec![coin("denom1", 200)]),
+                balance("account4", vec![coin("denom2", 300)]),
+                balance("account5", vec![coin("denom1", 50)]),
+            ],
+            outputs: vec![
+                balance("recipient1", vec![coin("denom1", 500)]),
+                balance("recipient2", vec![coin("denom1", 700), coin("denom2", 150)]),
+                balance("recipient3", vec![coin("denom2", 250)]),
+                balance("recipient4", vec![coin("denom1", 50)]),
+            ],
+            memo: Some("payroll".to_string()),
+        };
+
+        let chunks = multi_send.chunk(2);
+        assert!(chunks.len() > 1);
+        for chunk in chunks.iter() {
+            chunk.validate_multi_send_tx()?;
+            chunk.validate_structure()?;
+            assert!(chunk.inputs.len() <= 2 && chunk.outputs.len() <= 2);
+            assert_eq!(chunk.memo, multi_send.memo);
+        }
+
+        //Together the chunks move exactly what the tx moves
+        let merged = |side: fn(&MultiSend) -> &Vec<Balance>| {
+            normalize_balances(&chunks.iter().flat_map(side).cloned().collect::<Vec<_>>())
+        };
+        assert_eq!(
+            merged(|chunk| &chunk.inputs),
+            normalize_balances(&multi_send.inputs)
+        );
+        assert_eq!(
+            merged(|chunk| &chunk.outputs),
+            normalize_balances(&multi_send.outputs)
+        );
+
+        //A tx within the limit is a single chunk
+        assert_eq!(multi_send.chunk(5).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_coin_meta_passes_through() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        let meta = serde_json::json!({ "order_id": 42 });
+        multi_send.inputs[0].coins[0].meta = Some(meta.clone());
+        let recipient_coin = |result: &TxResult| {
+            result
+                .balance_changes
+                .iter()
+                .find(|balance| balance.address == "account_recipient")
+                .map(|balance| balance.coins[0].clone())
+        };
+
+        //Gross amounts take the burn out of the recipient's coin, so it loses the meta
+        let options = TxOptions {
+            amount_semantics: AmountSemantics::Gross,
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &options,
+        )?;
+        assert_eq!(recipient_coin(&result).ok_or("no recipient")?.meta, None);
+
+        definitions[0] = definitions[0].clone().with_burn_rate(0_f64);
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        assert_eq!(
+            recipient_coin(&result),
+            Some(Coin {
+                denom: "denom1".to_string(),
+                amount: 100,
+                meta: Some(meta),
+            })
+        );
+        //The sender's debit is a change, not the coin it sent
+        assert!(result
+            .balance_changes
+            .iter()
+            .filter(|balance| balance.address == "account1")
+            .all(|balance| balance.coins[0].meta.is_none()));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_issuer_credited_sum_of_commission_shares() -> Result<(), Box<dyn Error>> {
+        let original_balances = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            },
+            Balance {
+                address: "account3".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 1000,
+                    meta: None,
+                }],
+            },
+        ];
+        let definitions = vec![DenomDefinition {
+            denom: "denom1".to_string(),
+            issuer: "issuer_account_A".to_string(),
+            burn_rate: 0_f64,
+            commission_rate: 0.03,
+            ..DenomDefinition::default()
+        }];
+        //Raw commission shares of 3.3, 3.3 and 3.39 each round up to 4
+        let multi_send = MultiSend {
+            inputs: ["account1", "account2", "account3"]
+                .iter()
+                .zip([110, 110, 113])
+                .map(|(address, amount)| Balance {
+                    address: address.to_string(),
+                    coins: vec![Coin {
+                        denom: "denom1".to_string(),
+                        amount,
+                        meta: None,
+                    }],
+                })
+                .collect(),
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 333,
+                    meta: None,
+                }],
+            }],
+            memo: None,
+        };
+
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let shares = result
+            .fee_shares
+            .iter()
+            .map(|share| share.commission)
+            .collect::<Vec<i128>>();
+        assert_eq!(shares, vec![4, 4, 4]);
+        let issuer_credit = result
+            .balance_changes
+            .iter()
+            .find(|balance| balance.address == "issuer_account_A")
+            .map(|balance| balance.coins[0].amount);
+        assert_eq!(issuer_credit, Some(shares.iter().sum()));
+        verify_commission_credit(&result, "denom1")?;
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_allow_negative_skips_balance_check() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        //The module account holds 50 but sends 100 plus a burn of 10
+        original_balances[0].address = "module_account".to_string();
+        original_balances[0].coins[0].amount = 50;
+        multi_send.inputs[0].address = "module_account".to_string();
+
+        let rejected = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &TxOptions::default(),
+        );
+        assert!(matches!(rejected, Err(TxError::InsufficientBalance { .. })));
+
+        let options = TxOptions {
+            allow_negative: HashSet::from(["module_account".to_string()]),
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+        let change = result
+            .balance_changes
+            .iter()
+            .find(|balance| balance.address == "module_account")
+            .map(|balance| balance.coins[0].amount)
+            .ok_or("no module account change")?;
+        assert_eq!(change, -110);
+        Ok(())
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_supply_delta() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let deltas = supply_delta(original_balances, definitions, multi_send);
+        //denom1 burns roundup(1000 * 0.08), denom2 burns its whole 1000 at a burn rate of 1
+        assert_eq!(
+            deltas,
+            HashMap::from([("denom1".to_string(), -80), ("denom2".to_string(), -1000)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_strict_balance_entries() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //account1 is listed, but without a denom1 entry
+        original_balances[0].coins[0].denom = "denom2".to_string();
+
+        let strict = TxOptions {
+            strict_balance_entries: true,
+            ..TxOptions::default()
+        };
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &strict,
+        );
+        assert_eq!(
+            result.err(),
+            Some(TxError::MissingBalanceEntry {
+                address: "account1".to_string(),
+                denom: "denom1".to_string(),
+            })
+        );
+
+        //Leniently the missing entry is a zero balance, which can't cover the send
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &TxOptions::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(TxError::InsufficientBalance { available: 0, .. })
+        ));
+        Ok(())
+    }
 
-    //Process the output amounts
-    for output in tx_data.multi_send_tx.outputs.iter() {
-        for coin in output.coins.iter() {
-            //Update the senders balance in the coin_balance_changes hashmap
-            if let Some(coin_map) = tx_data.coin_balance_changes_map.get_mut(&output.address) {
-                if let Some(coin_amount) = coin_map.get_mut(&coin.denom) {
-                    *coin_amount += coin.amount
-                } else {
-                    coin_map.insert(coin.denom.clone(), coin.amount);
-                }
-            } else {
-                let mut coin_map = HashMap::new();
-                coin_map.insert(coin.denom.clone(), coin.amount);
-                tx_data
-                    .coin_balance_changes_map
-                    .insert(output.address.clone(), coin_map);
-            }
-        }
+    #[test]
+    //NOTE: Example #2 from README
+    pub fn test_required_balances() -> Result<(), Box<dyn Error>> {
+        let (_, definitions, multi_send) = initialize_issuer_exists_on_sender_receiver();
+        let required = required_balances(definitions, multi_send);
+        //650 sent, 26 burnt and 39 commission
+        assert_eq!(
+            required.get(&("account1".to_string(), "denom1".to_string())),
+            Some(&(650 + 26 + 39))
+        );
+        assert_eq!(
+            required.get(&("account2".to_string(), "denom1".to_string())),
+            Some(&(350 + 14 + 21))
+        );
+        assert_eq!(required.len(), 2);
+        Ok(())
     }
 
-    //Return the processed balances as a vector.
-    Ok(tx_data.collect_balance_changes())
-}
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_ledger_entries_balance() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let result =
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send)?;
+        let entries = to_ledger_entries(&result);
+        let entry =
+            |debit_account: &str, credit_account: &str, denom: &str, amount: i128| LedgerEntry {
+                debit_account: debit_account.to_string(),
+                credit_account: credit_account.to_string(),
+                denom: denom.to_string(),
+                amount,
+            };
+        assert_eq!(
+            entries,
+            vec![
+                entry("account1", "account_recipient", "denom1", 1000),
+                entry("account1", BURN_ACCOUNT, "denom1", 80),
+                entry("account1", "issuer_account_A", "denom1", 120),
+                entry("account2", "account_recipient", "denom2", 1000),
+                entry("account2", BURN_ACCOUNT, "denom2", 1000),
+            ]
+        );
 
-fn min(a: i128, b: i128) -> i128 {
-    if a < b {
-        a
-    } else {
-        b
+        //Per denom, the debits are the senders' changes and the credits the recipients' plus the burn
+        let mut ledger_net: HashMap<(String, String), i128> = HashMap::new();
+        for entry in entries.iter() {
+            *ledger_net
+                .entry((entry.debit_account.clone(), entry.denom.clone()))
+                .or_insert(0) -= entry.amount;
+            *ledger_net
+                .entry((entry.credit_account.clone(), entry.denom.clone()))
+                .or_insert(0) += entry.amount;
+        }
+        for balance in result.balance_changes.iter() {
+            for coin in balance.coins.iter() {
+                let key = (balance.address.clone(), coin.denom.clone());
+                assert_eq!(ledger_net.remove(&key), Some(coin.amount));
+            }
+        }
+        assert_eq!(
+            ledger_net,
+            HashMap::from([
+                ((BURN_ACCOUNT.to_string(), "denom1".to_string()), 80),
+                ((BURN_ACCOUNT.to_string(), "denom2".to_string()), 1000),
+            ])
+        );
+        Ok(())
     }
-}
-
-//roundup(total_burn * input_from_account / non_issuer_input_sum)
-fn evaluate_rate(amount: i128, rate: f64, total_amount: i128, non_issuer_input_sum: i128) -> i128 {
-    roundup((total_amount as f64 * rate) * amount as f64 / non_issuer_input_sum as f64)
-}
 
-//Helper function to round up an f64 to an i128
-fn roundup(n: f64) -> i128 {
-    (n + 0.5) as i128
-}
+    #[test]
+    pub fn test_output_to_burn_address_is_burnt() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        definitions[0] = definitions[0].clone().with_burn_rate(0_f64);
+        let burn_address = "core1000000000000000000000000000000000000".to_string();
+        multi_send.outputs[0].address = burn_address.clone();
+        let options = TxOptions {
+            burn_addresses: HashSet::from([burn_address.clone()]),
+            ..TxOptions::default()
+        };
 
-#[cfg(test)]
-mod tests {
-    use crate::calculate_balance_changes;
-    use crate::{Balance, Coin, DenomDefinition, MultiSend};
-    use std::collections::HashMap;
-    use std::error::Error;
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &options,
+        )?;
+        assert!(result
+            .balance_changes
+            .iter()
+            .all(|balance| balance.address != burn_address));
+        assert_eq!(
+            result.burnt_outputs,
+            HashMap::from([("denom1".to_string(), 100)])
+        );
+        assert_eq!(result.stats.total_burnt, 100);
+        assert_eq!(
+            normalize_balances(&result.balance_changes),
+            vec![Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: -100,
+                    meta: None,
+                }],
+            }]
+        );
+        Ok(())
+    }
 
     #[test]
-    //NOTE: Example #4 from README
-    pub fn test_invalid_sum() -> Result<(), Box<dyn Error>> {
-        let (original_balances, definitions, multi_send) = initialize_invalid_sum_data();
+    //NOTE: Example #1 from README
+    pub fn test_protocol_revenue() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let revenue = protocol_revenue(original_balances, definitions, multi_send)?;
         assert_eq!(
-            calculate_balance_changes(original_balances, definitions, multi_send).err(),
-            Some("Invalid Multi Send Tx".to_string())
+            revenue.burnt,
+            HashMap::from([("denom1".to_string(), 80), ("denom2".to_string(), 1000)])
+        );
+        assert_eq!(
+            revenue.commission,
+            HashMap::from([("denom1".to_string(), 120), ("denom2".to_string(), 0)])
+        );
+        assert_eq!(
+            revenue.total_fees_per_denom(),
+            HashMap::from([("denom1".to_string(), 200), ("denom2".to_string(), 1000)])
         );
         Ok(())
     }
 
     #[test]
     //NOTE: Example #1 from README
-    pub fn test_no_issuer_on_sender_or_receiver() -> Result<(), Box<dyn Error>> {
+    pub fn test_burn_budget() -> Result<(), Box<dyn Error>> {
         let (original_balances, definitions, multi_send) =
             initialize_no_issuer_on_sender_or_receiver();
-        let mut assertion_map = HashMap::new();
-        assertion_map.insert(
-            "account_recipient".to_string(),
-            vec![
+        let with_budget = |budget: i128| TxOptions {
+            max_total_burn: HashMap::from([("denom2".to_string(), budget)]),
+            ..TxOptions::default()
+        };
+
+        //denom2 burns the full 1000 sent at a burn rate of 1
+        let result = calculate_balance_changes_with_options(
+            original_balances.clone(),
+            definitions.clone(),
+            multi_send.clone(),
+            &with_budget(500),
+        );
+        assert_eq!(
+            result.err(),
+            Some(TxError::BurnBudgetExceeded {
+                denom: "denom2".to_string(),
+                burn: 1000,
+                budget: 500,
+            })
+        );
+
+        let result = calculate_balance_changes_with_options(
+            original_balances,
+            definitions,
+            multi_send,
+            &with_budget(1000),
+        )?;
+        assert_eq!(result.stats.total_burnt, 1080);
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_from_ndjson() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        let line = serde_json::to_string(&multi_send)?;
+        let input = format!("{}\n{}\n\n{}\n", line, line, line);
+
+        let txs = from_ndjson(&input)?;
+        assert_eq!(txs, vec![multi_send.clone(); 3]);
+        //Each replayed tx sends 100 and burns 10 from account1's 1000
+        let final_balances = apply_batch(original_balances, definitions, txs)?;
+        let account1 = final_balances
+            .iter()
+            .find(|balance| balance.address == "account1")
+            .map(|balance| balance.coins[0].amount);
+        assert_eq!(account1, Some(1000 - 3 * 110));
+
+        let malformed = format!("{}\n\n{{\"inputs\": [}}\n{}", line, line);
+        assert!(matches!(
+            from_ndjson(&malformed),
+            Err(TxError::InvalidNdjson { line: 3, .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_net_threshold() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, mut definitions, multi_send) =
+            initialize_single_transfer_data();
+        definitions[0] = definitions[0].clone().with_burn_rate(0_f64);
+        original_balances.push(Balance {
+            address: "account2".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        });
+        //account1 sends 10 and gets back `received`, account2 pays the rest of the recipient's 100
+        let net_of = |received: i128| -> Result<Option<i128>, Box<dyn Error>> {
+            let mut multi_send = multi_send.clone();
+            multi_send.inputs[0].coins[0].amount = 10;
+            multi_send.inputs.push(Balance {
+                address: "account2".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 90 + received,
+                    meta: None,
+                }],
+            });
+            multi_send.outputs.push(Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: received,
+                    meta: None,
+                }],
+            });
+            let options = TxOptions {
+                net_threshold: 3,
+                ..TxOptions::default()
+            };
+            let result = calculate_balance_changes_with_options(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send,
+                &options,
+            )?;
+            assert!(result
+                .balance_changes
+                .iter()
+                .all(|balance| !balance.coins.is_empty()));
+            Ok(result
+                .balance_changes
+                .iter()
+                .find(|balance| balance.address == "account1")
+                .map(|balance| balance.coins[0].amount))
+        };
+
+        assert_eq!(net_of(12)?, None);
+        assert_eq!(net_of(15)?, Some(5));
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_validation_report() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, mut definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        //account1 holds 50 of the 100 it sends, whose definition burns 120%
+        original_balances[0].coins[0].amount = 50;
+        definitions[0] = definitions[0].clone().with_burn_rate(1.2);
+        original_balances.push(Balance {
+            address: "account2".to_string(),
+            coins: vec![Coin {
+                denom: "denom1".to_string(),
+                amount: 1000,
+                meta: None,
+            }],
+        });
+        multi_send.inputs.push(Balance {
+            address: "account2".to_string(),
+            coins: vec![
                 Coin {
                     denom: "denom1".to_string(),
-                    amount: 1000,
+                    amount: 10,
+                    meta: None,
                 },
                 Coin {
-                    denom: "denom2".to_string(),
-                    amount: 1000,
+                    denom: "denomX".to_string(),
+                    amount: 10,
+                    meta: None,
                 },
             ],
+        });
+
+        let report = validation_report(original_balances, definitions, multi_send);
+        assert_eq!(
+            report,
+            ValidationReport {
+                sum_ok: false,
+                unknown_denoms: vec!["denomX".to_string()],
+                invalid_rates: vec!["denom1".to_string()],
+                insufficient: vec![("account1".to_string(), "denom1".to_string())],
+            }
         );
-        assertion_map.insert(
-            "issuer_account_A".to_string(),
-            vec![Coin {
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_cross_denom_mint_rejected() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        //account1 sends 100 denom1, the recipient is credited 100 denom2 that nobody sent
+        multi_send.inputs.truncate(1);
+        multi_send.inputs[0].coins[0].amount = 100;
+        multi_send.outputs[0].coins = vec![Coin {
+            denom: "denom2".to_string(),
+            amount: 100,
+            meta: None,
+        }];
+        assert_eq!(
+            calculate_balance_changes_detailed(original_balances, definitions, multi_send).err(),
+            Some(TxError::InvalidSum)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_negative_input_rejected() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, mut multi_send) = initialize_single_transfer_data();
+        //A negative input paired with a negative output would debit the victim without its consent
+        multi_send.inputs[0].address = "thief".to_string();
+        multi_send.inputs[0].coins[0].amount = -100;
+        multi_send.outputs[0].address = "account1".to_string();
+        multi_send.outputs[0].coins[0].amount = -100;
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::NegativeAmount {
+                address: "thief".to_string(),
                 denom: "denom1".to_string(),
-                amount: 120,
-            }],
+            })
         );
-        assertion_map.insert(
-            "account1".to_string(),
-            vec![Coin {
+
+        //The total across the inputs is 1, but summing account1's denom1 inputs would overflow first
+        let input = |address: &str, amount: i128| Balance {
+            address: address.to_string(),
+            coins: vec![Coin {
                 denom: "denom1".to_string(),
-                amount: -1200,
+                amount,
+                meta: None,
             }],
+        };
+        multi_send.inputs = vec![
+            input("account1", i128::MAX),
+            input("thief", -i128::MAX),
+            input("account1", 1),
+        ];
+        multi_send.outputs = vec![input("account_recipient", 1)];
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances,
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::NegativeAmount {
+                address: "thief".to_string(),
+                denom: "denom1".to_string(),
+            })
         );
-        assertion_map.insert(
-            "account2".to_string(),
-            vec![Coin {
-                denom: "denom2".to_string(),
-                amount: -2000,
-            }],
+        //Analysis of an unvalidated tx reports an overflowing sum
+        multi_send.inputs = vec![input("account1", i128::MAX), input("account2", 1)];
+        assert_eq!(
+            theoretical_fees(&definitions, &multi_send, "denom1").err(),
+            Some(TxError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_gross_rejects_fees_over_100_percent() -> Result<(), Box<dyn Error>> {
+        let (original_balances, mut definitions, multi_send) = initialize_single_transfer_data();
+        //120 in fees on the 100 sent would debit the recipient 20
+        definitions[0].burn_rate = 0.7;
+        definitions[0].commission_rate = 0.5;
+        let options = TxOptions {
+            amount_semantics: AmountSemantics::Gross,
+            ..TxOptions::default()
+        };
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances,
+                definitions,
+                multi_send,
+                &options
+            )
+            .err(),
+            Some(TxError::ExcessiveFees {
+                denom: "denom1".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_gross_deduction_overflow() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, mut multi_send) =
+            initialize_single_transfer_data();
+        //The fees times the output amount don't fit in an i128
+        original_balances[0].coins[0].amount = i128::MAX / 4;
+        multi_send.inputs[0].coins[0].amount = i128::MAX / 4;
+        multi_send.outputs[0].coins[0].amount = i128::MAX / 4;
+        let options = TxOptions {
+            amount_semantics: AmountSemantics::Gross,
+            ..TxOptions::default()
+        };
+        assert_eq!(
+            calculate_balance_changes_with_options(
+                original_balances,
+                definitions,
+                multi_send,
+                &options
+            )
+            .err(),
+            Some(TxError::Overflow)
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_duplicate_balances_overflow() -> Result<(), Box<dyn Error>> {
+        let (mut original_balances, definitions, multi_send) = initialize_single_transfer_data();
+        //Two rows of account1's denom1 at i128::MAX can't be summed
+        original_balances[0].coins[0].amount = i128::MAX;
+        original_balances.push(original_balances[0].clone());
+
+        assert_eq!(
+            Balances::try_from(original_balances.clone()).err(),
+            Some(TxError::Overflow)
+        );
+        assert_eq!(
+            calculate_balance_changes_detailed(
+                original_balances.clone(),
+                definitions.clone(),
+                multi_send.clone()
+            )
+            .err(),
+            Some(TxError::Overflow)
+        );
+        assert_eq!(
+            calculate_balance_changes_with_store(
+                &original_balances,
+                definitions,
+                multi_send,
+                &TxOptions::default()
+            )
+            .err(),
+            Some(TxError::Overflow)
+        );
+
+        let mut balances = Balances::default();
+        balances.credit("account1", "denom1", i128::MAX)?;
+        assert_eq!(
+            balances.credit("account1", "denom1", 1),
+            Err(TxError::Overflow)
+        );
+        assert_eq!(
+            balances.debit("account2", "denom1", i128::MIN),
+            Err(TxError::Overflow)
+        );
+        Ok(())
+    }
+
+    //Store that records every lookup made against it
+    struct RecordingStore {
+        balances: Vec<Balance>,
+        queried: RefCell<Vec<(String, String)>>,
+    }
+
+    impl BalanceStore for RecordingStore {
+        fn get(&self, address: &str, denom: &str) -> Result<i128, TxError> {
+            self.queried
+                .borrow_mut()
+                .push((address.to_string(), denom.to_string()));
+            self.balances.get(address, denom)
+        }
+    }
+
+    #[test]
+    //NOTE: Example #1 from README
+    pub fn test_balance_store() -> Result<(), Box<dyn Error>> {
+        let (original_balances, definitions, multi_send) =
+            initialize_no_issuer_on_sender_or_receiver();
+        let store = RecordingStore {
+            balances: original_balances.clone(),
+            queried: RefCell::new(vec![]),
+        };
+
+        let with_store = calculate_balance_changes_with_store(
+            &store,
+            definitions.clone(),
+            multi_send.clone(),
+            &TxOptions::default(),
+        )?;
+        assert_eq!(
+            *store.queried.borrow(),
+            vec![
+                ("account1".to_string(), "denom1".to_string()),
+                ("account2".to_string(), "denom2".to_string()),
+            ]
+        );
+
+        let in_memory = calculate_balance_changes_with_store(
+            &original_balances,
+            definitions,
+            multi_send,
+            &TxOptions::default(),
+        )?;
+        assert_eq!(with_store.stats.total_burnt, in_memory.stats.total_burnt);
+        Ok(())
+    }
+
+    //Asserts the balance changes match the expected coins per address, ignoring the order of the
+    //entries and coins. On mismatch, panics listing every (address, denom) that differs.
+    fn assert_changes_eq(result: &[Balance], expected: &HashMap<String, Vec<Coin>>) {
+        let flatten = |balances: Vec<Balance>| {
+            normalize_balances(&balances)
+                .into_iter()
+                .flat_map(|balance| {
+                    balance
+                        .coins
+                        .into_iter()
+                        .map(move |coin| ((balance.address.clone(), coin.denom), coin.amount))
+                })
+                .collect::<BTreeMap<(String, String), i128>>()
+        };
+        let actual = flatten(result.to_vec());
+        let expected = flatten(
+            expected
+                .iter()
+                .map(|(address, coins)| Balance {
+                    address: address.clone(),
+                    coins: coins.clone(),
+                })
+                .collect(),
         );
 
-        let balance_changes =
-            calculate_balance_changes(original_balances, definitions, multi_send).unwrap();
-        for balance_change in balance_changes.iter() {
-            assertion_map
-                .get(&balance_change.address)
-                .unwrap()
-                .iter()
-                .zip(balance_change.coins.clone())
-                .for_each(|(assertion_coin, coin)| {
-                    assert_eq!(assertion_coin.amount, coin.amount);
-                })
+        let show = |amount: Option<&i128>| amount.map_or("<missing>".to_string(), i128::to_string);
+        let diff = actual
+            .keys()
+            .chain(expected.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|key| actual.get(key) != expected.get(key))
+            .map(|key| {
+                format!(
+                    "  {} {}: expected {}, got {}",
+                    key.0,
+                    key.1,
+                    show(expected.get(key)),
+                    show(actual.get(key))
+                )
+            })
+            .collect::<Vec<String>>();
+        assert!(
+            diff.is_empty(),
+            "balance changes differ:\n{}",
+            diff.join("\n")
+        );
+    }
+
+    //Test setup helper functions
+
+    //Two senders of denom1 at a 2% burn rate, where account1's share is a float just above 6
+    fn initialize_float_error_data() -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let coin = |amount: i128| Coin {
+            denom: "denom1".to_string(),
+            amount,
+            meta: None,
+        };
+        let original_balances = vec![
+            Balance {
+                address: "account1".to_string(),
+                coins: vec![coin(1000)],
+            },
+            Balance {
+                address: "account2".to_string(),
+                coins: vec![coin(1000)],
+            },
+        ];
+        let definitions = vec![DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0.02)];
+        let multi_send = MultiSend {
+            inputs: vec![
+                Balance {
+                    address: "account1".to_string(),
+                    coins: vec![coin(300)],
+                },
+                Balance {
+                    address: "account2".to_string(),
+                    coins: vec![coin(30)],
+                },
+            ],
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![coin(330)],
+            }],
+            memo: None,
+        };
+        (original_balances, definitions, multi_send)
+    }
+
+    //Runs a fee-free tx on the fast path and on the general path
+    fn run_both_paths(
+        original_balances: Vec<Balance>,
+        definitions: Vec<DenomDefinition>,
+        multi_send: MultiSend,
+        options: &TxOptions,
+    ) -> Result<(TxResult, TxResult), TxError> {
+        let tx_data = TxData::prepared(multi_send, original_balances, definitions)?;
+        assert!(tx_data.fee_free);
+        let general = general_path(tx_data.clone());
+        Ok((
+            run_tx(tx_data, options, None)?,
+            run_tx(general, options, None)?,
+        ))
+    }
+
+    //Initialized tx data as built by `TxData::new`, and a copy whose maps start empty and grow
+    #[allow(deprecated)]
+    fn presized_and_growing(
+        original_balances: Vec<Balance>,
+        definitions: Vec<DenomDefinition>,
+        multi_send: MultiSend,
+    ) -> Result<(TxData, TxData), TxError> {
+        let presized = TxData::new(
+            multi_send,
+            Balances::try_from(original_balances)?,
+            definitions,
+        );
+        let mut growing = presized.clone();
+        growing.non_issuer_input_sum_map = HashMap::new();
+        growing.non_issuer_output_sum_map = HashMap::new();
+        growing.coin_balance_changes_map = HashMap::new();
+        let mut initialized = [presized, growing];
+        for tx_data in initialized.iter_mut() {
+            tx_data.initialize_definitions_map();
+            tx_data.initialize_fee_data()?;
         }
-        Ok(())
+        let [presized, growing] = initialized;
+        Ok((presized, growing))
     }
-    #[test]
-    ///NOTE: Example #2 from README
-    pub fn test_issuer_exists_on_sender_receiver() -> Result<(), Box<dyn Error>> {
-        let (original_balances, definitions, multi_send) =
-            initialize_issuer_exists_on_sender_receiver();
-        let mut assertion_map = HashMap::new();
-        assertion_map.insert(
-            "account_recipient".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: 500,
+
+    //Prepared tx data forced onto the general path
+    #[allow(deprecated)]
+    fn general_path(mut tx_data: TxData) -> TxData {
+        tx_data.fee_free = false;
+        tx_data.initialize_bc_data().unwrap();
+        tx_data
+    }
+    //`count` senders each sending 10 denom1 to a single recipient, with burn 0.5 and commission 0.25
+    fn initialize_many_senders_data(
+        count: usize,
+    ) -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let senders = (0..count)
+            .map(|i| Balance {
+                address: format!("sender{}", i),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 10,
+                    meta: None,
+                }],
+            })
+            .collect::<Vec<Balance>>();
+        let original_balances = senders
+            .iter()
+            .map(|sender| Balance {
+                address: sender.address.clone(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
+            })
+            .collect();
+        let definitions = vec![DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0.5)
+            .with_commission_rate(0.25)];
+        let multi_send = MultiSend {
+            inputs: senders,
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 10 * count as i128,
+                    meta: None,
+                }],
             }],
-        );
-        assertion_map.insert(
-            "issuer_account_A".to_string(),
-            vec![Coin {
+            memo: None,
+        };
+        (original_balances, definitions, multi_send)
+    }
+
+    fn initialize_single_transfer_data() -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let original_balances = vec![Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
                 denom: "denom1".to_string(),
-                amount: 560,
+                amount: 1000,
+                meta: None,
             }],
-        );
-        assertion_map.insert(
-            "account1".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: -715,
+        }];
+        let definitions = vec![DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0.1_f64)
+            .with_commission_rate(0_f64)];
+        let multi_send: MultiSend = MultiSend {
+            inputs: vec![Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
             }],
-        );
-        assertion_map.insert(
-            "account2".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: -385,
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
             }],
-        );
+            memo: None,
+        };
 
-        let balance_changes =
-            calculate_balance_changes(original_balances, definitions, multi_send).unwrap();
-        for balance_change in balance_changes.iter() {
-            assertion_map
-                .get(&balance_change.address)
-                .unwrap()
-                .iter()
-                .zip(balance_change.coins.clone())
-                .for_each(|(assertion_coin, coin)| {
-                    assert_eq!(assertion_coin.amount, coin.amount);
-                })
-        }
-        Ok(())
+        (original_balances, definitions, multi_send)
     }
 
-    #[test]
-    ///NOTE: Example #2 from README
-    pub fn test_demonstrate_rounding_up() -> Result<(), Box<dyn Error>> {
-        let (original_balances, definitions, multi_send) = initialize_rounding_up_data();
-        let mut assertion_map = HashMap::new();
-        assertion_map.insert(
-            "account_recipient".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: 2,
-            }],
-        );
-        assertion_map.insert(
-            "issuer_account_A".to_string(),
-            vec![Coin {
+    fn initialize_excessive_fees_data() -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
+        let original_balances = vec![Balance {
+            address: "account1".to_string(),
+            coins: vec![Coin {
                 denom: "denom1".to_string(),
-                amount: 2,
+                amount: 1000,
+                meta: None,
             }],
-        );
-        assertion_map.insert(
-            "account1".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: -1, //This is kind of a ? from example #5.. I think it should be -1, but don't really understand how the amount could come out a +3 since the user is spending not receiving
+        }];
+        let definitions = vec![DenomDefinition::default()
+            .with_denom("denom1")
+            .with_issuer("issuer_account_A")
+            .with_burn_rate(0.7_f64)
+            .with_commission_rate(0.5_f64)];
+        let multi_send: MultiSend = MultiSend {
+            inputs: vec![Balance {
+                address: "account1".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
             }],
-        );
-        assertion_map.insert(
-            "account2".to_string(),
-            vec![Coin {
-                denom: "denom1".to_string(),
-                amount: -1, //This is kind of a ? from example #5.. I think it should be -1, but don't really understand how the amount could come out a +3 since the user is spending not receiving
+            outputs: vec![Balance {
+                address: "account_recipient".to_string(),
+                coins: vec![Coin {
+                    denom: "denom1".to_string(),
+                    amount: 100,
+                    meta: None,
+                }],
             }],
-        );
+            memo: None,
+        };
 
-        let balance_changes =
-            calculate_balance_changes(original_balances, definitions, multi_send).unwrap();
-        for balance_change in balance_changes.iter() {
-            assertion_map
-                .get(&balance_change.address)
-                .unwrap()
-                .iter()
-                .zip(balance_change.coins.clone())
-                .for_each(|(assertion_coin, coin)| {
-                    assert_eq!(assertion_coin.amount, coin.amount);
-                })
-        }
-        Ok(())
-    }
-    #[test]
-    ///NOTE: Example #3 from README
-    pub fn test_insufficient_balance() -> Result<(), Box<dyn Error>> {
-        let (original_balances, definitions, multi_send) = initialize_insufficient_balance_data();
-        assert_eq!(
-            calculate_balance_changes(original_balances, definitions, multi_send).err(),
-            Some(format!(
-                "Inssuficient wallet balance on {} for coin {}",
-                "account1", "denom1"
-            ))
-        );
-        Ok(())
+        (original_balances, definitions, multi_send)
     }
 
-    //Test setup helper functions
     fn initialize_insufficient_balance_data() -> (Vec<Balance>, Vec<DenomDefinition>, MultiSend) {
         let mut original_balances: Vec<Balance> = vec![];
         let mut definitions: Vec<DenomDefinition> = vec![];
@@ -569,18 +8683,18 @@ mod tests {
             address: "account1".to_string(),
             coins: vec![],
         });
-        definitions.push(DenomDefinition {
-            denom: "denom1".to_string(),
-            issuer: "issuer_account_A".to_string(),
-            burn_rate: 0_f64,
-            commission_rate: 0_f64,
-        });
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A"),
+        );
         let multi_send: MultiSend = MultiSend {
             inputs: vec![Balance {
                 address: "account1".to_string(),
                 coins: vec![Coin {
                     denom: "denom1".to_string(),
                     amount: 350,
+                    meta: None,
                 }],
             }],
             outputs: vec![Balance {
@@ -588,8 +8702,10 @@ mod tests {
                 coins: vec![Coin {
                     denom: "denom1".to_string(),
                     amount: 350,
+                    meta: None,
                 }],
             }],
+            memo: None,
         };
 
         (original_balances, definitions, multi_send)
@@ -604,6 +8720,7 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom1".to_string(),
                 amount: 1000,
+                meta: None,
             }],
         });
         original_balances.push(Balance {
@@ -611,15 +8728,17 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom1".to_string(),
                 amount: 1000,
+                meta: None,
             }],
         });
 
-        definitions.push(DenomDefinition {
-            denom: "denom1".to_string(),
-            issuer: "issuer_account_A".to_string(),
-            burn_rate: 0.01_f64,
-            commission_rate: 0.01_f64,
-        });
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0.01_f64)
+                .with_commission_rate(0.01_f64),
+        );
         let multi_send: MultiSend = MultiSend {
             inputs: vec![
                 Balance {
@@ -627,6 +8746,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 1,
+                        meta: None,
                     }],
                 },
                 Balance {
@@ -634,6 +8754,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 1,
+                        meta: None,
                     }],
                 },
             ],
@@ -643,8 +8764,10 @@ mod tests {
                 coins: vec![Coin {
                     denom: "denom1".to_string(),
                     amount: 2,
+                    meta: None,
                 }],
             }],
+            memo: None,
         };
 
         (original_balances, definitions, multi_send)
@@ -660,20 +8783,23 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom1".to_string(),
                 amount: 1_000_000,
+                meta: None,
             }],
         });
-        definitions.push(DenomDefinition {
-            denom: "denom1".to_string(),
-            issuer: "issuer_account_A".to_string(),
-            burn_rate: 0_f64,
-            commission_rate: 0_f64,
-        });
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0_f64)
+                .with_commission_rate(0_f64),
+        );
         let multi_send: MultiSend = MultiSend {
             inputs: vec![Balance {
                 address: "account1".to_string(),
                 coins: vec![Coin {
                     denom: "denom1".to_string(),
                     amount: 350,
+                    meta: None,
                 }],
             }],
             outputs: vec![Balance {
@@ -681,8 +8807,10 @@ mod tests {
                 coins: vec![Coin {
                     denom: "denom1".to_string(),
                     amount: 450,
+                    meta: None,
                 }],
             }],
+            memo: None,
         };
 
         (original_balances, definitions, multi_send)
@@ -698,6 +8826,7 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom1".to_string(),
                 amount: 1_000_000,
+                meta: None,
             }],
         });
         original_balances.push(Balance {
@@ -705,20 +8834,23 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom2".to_string(),
                 amount: 1_000_000,
+                meta: None,
             }],
         });
-        definitions.push(DenomDefinition {
-            denom: "denom1".to_string(),
-            issuer: "issuer_account_A".to_string(),
-            burn_rate: 0.08_f64,
-            commission_rate: 0.12_f64,
-        });
-        definitions.push(DenomDefinition {
-            denom: "denom2".to_string(),
-            issuer: "issuer_account_B".to_string(),
-            burn_rate: 1_f64,
-            commission_rate: 0_f64,
-        });
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0.08_f64)
+                .with_commission_rate(0.12_f64),
+        );
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom2")
+                .with_issuer("issuer_account_B")
+                .with_burn_rate(1_f64)
+                .with_commission_rate(0_f64),
+        );
         let multi_send: MultiSend = MultiSend {
             inputs: vec![
                 Balance {
@@ -726,6 +8858,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 1000,
+                        meta: None,
                     }],
                 },
                 Balance {
@@ -733,6 +8866,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom2".to_string(),
                         amount: 1000,
+                        meta: None,
                     }],
                 },
             ],
@@ -742,13 +8876,16 @@ mod tests {
                     Coin {
                         denom: "denom1".to_string(),
                         amount: 1000,
+                        meta: None,
                     },
                     Coin {
                         denom: "denom2".to_string(),
                         amount: 1000,
+                        meta: None,
                     },
                 ],
             }],
+            memo: None,
         };
 
         (original_balances, definitions, multi_send)
@@ -764,21 +8901,24 @@ mod tests {
             coins: vec![Coin {
                 denom: "denom1".to_string(),
                 amount: 1_000_000,
+                meta: None,
             }],
         });
         original_balances.push(Balance {
             address: "account2".to_string(),
             coins: vec![Coin {
-                denom: "denom2".to_string(),
+                denom: "denom1".to_string(),
                 amount: 1_000_000,
+                meta: None,
             }],
         });
-        definitions.push(DenomDefinition {
-            denom: "denom1".to_string(),
-            issuer: "issuer_account_A".to_string(),
-            burn_rate: 0.08_f64,
-            commission_rate: 0.12_f64,
-        });
+        definitions.push(
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0.08_f64)
+                .with_commission_rate(0.12_f64),
+        );
 
         let multi_send: MultiSend = MultiSend {
             inputs: vec![
@@ -787,6 +8927,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 650,
+                        meta: None,
                     }],
                 },
                 Balance {
@@ -794,6 +8935,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 350,
+                        meta: None,
                     }],
                 },
             ],
@@ -803,6 +8945,7 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 500,
+                        meta: None,
                     }],
                 },
                 Balance {
@@ -810,11 +8953,159 @@ mod tests {
                     coins: vec![Coin {
                         denom: "denom1".to_string(),
                         amount: 500,
+                        meta: None,
                     }],
                 },
             ],
+            memo: None,
         };
 
         (original_balances, definitions, multi_send)
     }
 }
+
+//Malformed-but-parseable inputs must be rejected with an error, never a panic
+#[cfg(test)]
+mod panic_free_tests {
+    use crate::calculate_balance_changes;
+    use crate::{Balance, Coin, DenomDefinition, MultiSend};
+    use std::error::Error;
+    use std::panic;
+
+    fn assert_rejected(
+        original_balances: Vec<Balance>,
+        definitions: Vec<DenomDefinition>,
+        multi_send: MultiSend,
+    ) {
+        let result = panic::catch_unwind(|| {
+            calculate_balance_changes(original_balances, definitions, multi_send)
+        });
+        assert!(result.is_ok(), "calculate_balance_changes panicked");
+        assert!(result.unwrap().is_err());
+    }
+
+    fn coin(denom: &str, amount: i128) -> Coin {
+        Coin {
+            denom: denom.to_string(),
+            amount,
+            meta: None,
+        }
+    }
+
+    fn balance(address: &str, coins: Vec<Coin>) -> Balance {
+        Balance {
+            address: address.to_string(),
+            coins,
+        }
+    }
+
+    fn definitions() -> Vec<DenomDefinition> {
+        vec![
+            DenomDefinition::default()
+                .with_denom("denom1")
+                .with_issuer("issuer_account_A")
+                .with_burn_rate(0.1_f64)
+                .with_commission_rate(0.1_f64),
+            DenomDefinition::default()
+                .with_denom("denom2")
+                .with_issuer("issuer_account_B")
+                .with_burn_rate(0_f64)
+                .with_commission_rate(0_f64),
+        ]
+    }
+
+    fn transfer(coins: Vec<Coin>) -> MultiSend {
+        MultiSend {
+            inputs: vec![balance("account1", coins.clone())],
+            outputs: vec![balance("account_recipient", coins)],
+            memo: None,
+        }
+    }
+
+    #[test]
+    pub fn test_sender_missing_from_balances() -> Result<(), Box<dyn Error>> {
+        assert_rejected(
+            vec![balance("account2", vec![coin("denom1", 1000)])],
+            definitions(),
+            transfer(vec![coin("denom1", 100)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_sender_with_empty_coins() -> Result<(), Box<dyn Error>> {
+        assert_rejected(
+            vec![balance("account1", vec![])],
+            definitions(),
+            transfer(vec![coin("denom1", 100)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_balance_coins_in_different_order() -> Result<(), Box<dyn Error>> {
+        //Plenty of denom2 at index 0, but too little denom1 for the send + fees
+        assert_rejected(
+            vec![balance(
+                "account1",
+                vec![coin("denom2", 1_000_000), coin("denom1", 100)],
+            )],
+            definitions(),
+            transfer(vec![coin("denom1", 100), coin("denom2", 100)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_input_with_empty_coins() -> Result<(), Box<dyn Error>> {
+        assert_rejected(
+            vec![balance("account1", vec![coin("denom1", 1000)])],
+            definitions(),
+            MultiSend {
+                inputs: vec![balance("account1", vec![])],
+                outputs: vec![balance("account_recipient", vec![coin("denom1", 100)])],
+                memo: None,
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_inputs_without_outputs() -> Result<(), Box<dyn Error>> {
+        assert_rejected(
+            vec![balance("account1", vec![coin("denom1", 1000)])],
+            definitions(),
+            MultiSend {
+                inputs: vec![balance("account1", vec![coin("denom1", 100)])],
+                outputs: vec![],
+                memo: None,
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_debit_overflow() -> Result<(), Box<dyn Error>> {
+        //The 10% burn on top of the amount doesn't fit in an i128
+        assert_rejected(
+            vec![balance("account1", vec![coin("denom1", 5)])],
+            definitions(),
+            transfer(vec![coin("denom1", i128::MAX - 10)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn test_outputs_without_inputs() -> Result<(), Box<dyn Error>> {
+        assert_rejected(
+            vec![],
+            definitions(),
+            MultiSend {
+                inputs: vec![],
+                outputs: vec![balance("account_recipient", vec![coin("denom1", 100)])],
+                memo: None,
+            },
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use assert_cmd::Command;
+use std::error::Error;
+
+//Runs `validate` on the fixtures of a README example
+fn validate(example: &str) -> Result<assert_cmd::assert::Assert, Box<dyn Error>> {
+    let fixture = |kind: &str| format!("tests/fixtures/{}_{}.json", example, kind);
+    Ok(Command::cargo_bin("rust-task")?
+        .args(["validate", "--input", &fixture("tx")])
+        .args(["--balances", &fixture("balances")])
+        .args(["--denoms", &fixture("denoms")])
+        .assert())
+}
+
+#[test]
+//NOTE: Example #1 from README
+pub fn test_validate_accepts_valid_tx() -> Result<(), Box<dyn Error>> {
+    validate("example1")?.success().stdout("OK\n");
+    Ok(())
+}
+
+#[test]
+//NOTE: Example #3 from README
+pub fn test_validate_rejects_insufficient_balance() -> Result<(), Box<dyn Error>> {
+    let assert = validate("example3")?.code(1);
+    let stderr = String::from_utf8(assert.get_output().stderr.clone())?;
+    assert!(stderr.contains("InsufficientBalance"));
+    assert!(stderr.contains("account1"));
+    Ok(())
+}
+
+#[test]
+pub fn test_validate_missing_flag() -> Result<(), Box<dyn Error>> {
+    Command::cargo_bin("rust-task")?
+        .args(["validate", "--input", "tests/fixtures/example1_tx.json"])
+        .assert()
+        .code(2);
+    Ok(())
+}